@@ -1,12 +1,24 @@
 use std::any::Any;
 
-use crate::{interpreter::Eval, loxtype::LoxType, resolver::Resolve};
+use std::rc::Rc;
+
+use crate::{interpreter::Eval, loxtype::LoxType, resolver::Resolve, symbol::Symbol};
+
+use super::{Parameter, Statement};
 
 pub trait Expression: std::fmt::Debug + Eval + Resolve {
     fn as_any(&self) -> &dyn Any;
     fn into_any(self: Box<Self>) -> Box<dyn Any>;
 }
 
+/// Where the resolver found a local variable: the number of scopes between the use and the
+/// declaration, and the index of the variable within the declaring scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub distance: u32,
+    pub index: usize,
+}
+
 #[derive(Debug)]
 pub struct NilExpression();
 
@@ -22,21 +34,37 @@ pub struct NegExpression {
 #[derive(Debug)]
 pub struct NotExpression(pub Box<dyn Expression>);
 
+/// Bitwise complement, `~x`.
+#[derive(Debug)]
+pub struct BitNotExpression {
+    pub expression: Box<dyn Expression>,
+    pub line: u32,
+}
+
 #[derive(Debug)]
 pub struct GroupingExpression(pub Box<dyn Expression>);
 
 #[derive(Debug, Clone, Copy)]
 pub enum BinaryOperator {
     Add,
+    /// `..`, which joins the string forms of any two values.
+    Concat,
     Substract,
     Multiply,
     Divide,
+    Modulo,
+    Power,
     Equal,
     NotEqual,
     Less,
     LessOrEqual,
     Greater,
     GreaterOrEqual,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
 }
 
 #[derive(Debug)]
@@ -49,16 +77,16 @@ pub struct BinaryExpression {
 
 #[derive(Debug)]
 pub struct VariableExpression {
-    pub name: String,
-    pub maybe_distance: Option<u32>,
+    pub name: Symbol,
+    pub maybe_slot: Option<Slot>,
     pub line: u32,
 }
 
 #[derive(Debug)]
 pub struct AssignExpression {
-    pub name: String,
+    pub name: Symbol,
     pub value: Box<dyn Expression>,
-    pub maybe_distance: Option<u32>,
+    pub maybe_slot: Option<Slot>,
     pub line: u32,
 }
 
@@ -66,6 +94,8 @@ pub struct AssignExpression {
 pub enum LogicalOperator {
     And,
     Or,
+    /// `??`, which only falls back to the right operand when the left one is nil.
+    Coalesce,
 }
 
 #[derive(Debug)]
@@ -75,39 +105,95 @@ pub struct LogicalExpression {
     pub operator: LogicalOperator,
 }
 
+#[derive(Debug)]
+pub struct TernaryExpression {
+    pub condition: Box<dyn Expression>,
+    pub then_branch: Box<dyn Expression>,
+    pub else_branch: Box<dyn Expression>,
+}
+
 #[derive(Debug)]
 pub struct CallExpression {
     pub callee: Box<dyn Expression>,
     pub arguments: Vec<Box<dyn Expression>>,
+    /// Arguments written as `name: value`, which come after the positional ones.
+    pub keyword_arguments: Vec<(Symbol, Box<dyn Expression>)>,
     pub line: u32,
 }
 
+#[derive(Debug)]
+pub struct FunctionExpression {
+    pub parameters: Vec<Parameter>,
+    pub statements: Rc<Vec<Box<dyn Statement>>>,
+}
+
 #[derive(Debug)]
 pub struct GetExpression {
     pub object: Box<dyn Expression>,
-    pub name: String,
+    pub name: Symbol,
+    /// Written as `object?.name`, which gives nil when the object is nil.
+    pub is_optional: bool,
     pub line: u32,
 }
 
 #[derive(Debug)]
 pub struct SetExpression {
     pub object: Box<dyn Expression>,
-    pub name: String,
+    pub name: Symbol,
     pub value: Box<dyn Expression>,
+    /// Set for compound assignments like `a.b += c`, combining the current field value with `value`.
+    pub maybe_operator: Option<BinaryOperator>,
+    pub line: u32,
+}
+
+#[derive(Debug)]
+pub struct ListExpression {
+    pub elements: Vec<Box<dyn Expression>>,
+}
+
+#[derive(Debug)]
+pub struct MapExpression {
+    pub entries: Vec<(Box<dyn Expression>, Box<dyn Expression>)>,
+    pub line: u32,
+}
+
+#[derive(Debug)]
+pub struct IndexExpression {
+    pub object: Box<dyn Expression>,
+    pub index: Box<dyn Expression>,
+    pub line: u32,
+}
+
+#[derive(Debug)]
+pub struct IndexSetExpression {
+    pub object: Box<dyn Expression>,
+    pub index: Box<dyn Expression>,
+    pub value: Box<dyn Expression>,
+    /// Set for compound assignments like `a[i] += b`, combining the current element with `value`.
+    pub maybe_operator: Option<BinaryOperator>,
     pub line: u32,
 }
 
 #[derive(Debug)]
 pub struct ThisExpression {
     pub line: u32,
-    pub maybe_distance: Option<u32>,
+    pub maybe_slot: Option<Slot>,
 }
 
 #[derive(Debug)]
 pub struct SuperExpression {
-    pub method: String,
+    pub method: Symbol,
     pub line: u32,
-    pub maybe_distance: Option<u32>,
+    pub maybe_slot: Option<Slot>,
+}
+
+/// Evaluates `value` once and binds it to `name` in a scope of its own, in which `body` is
+/// evaluated. There's no syntax for it, the parser creates it when desugaring.
+#[derive(Debug)]
+pub struct LetExpression {
+    pub name: Symbol,
+    pub value: Box<dyn Expression>,
+    pub body: Box<dyn Expression>,
 }
 
 macro_rules! impl_expression {
@@ -131,14 +217,22 @@ impl_expression!(
     LiteralExpression,
     NegExpression,
     NotExpression,
+    BitNotExpression,
     GroupingExpression,
     BinaryExpression,
     VariableExpression,
     AssignExpression,
     LogicalExpression,
+    TernaryExpression,
     CallExpression,
+    FunctionExpression,
     GetExpression,
     SetExpression,
+    ListExpression,
+    MapExpression,
+    IndexExpression,
+    IndexSetExpression,
     ThisExpression,
     SuperExpression,
+    LetExpression,
 );
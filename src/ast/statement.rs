@@ -1,6 +1,6 @@
-use std::{collections::HashMap, fmt::Debug, rc::Rc};
+use std::{fmt::Debug, rc::Rc};
 
-use crate::{ast::VariableExpression, interpreter::Exec, resolver::Resolve};
+use crate::{ast::VariableExpression, interpreter::Exec, resolver::Resolve, symbol::Symbol};
 
 use super::Expression;
 
@@ -8,7 +8,8 @@ pub trait Statement: Debug + Exec + Resolve {}
 
 #[derive(Debug)]
 pub struct PrintStatement {
-    pub expression: Box<dyn Expression>,
+    /// Printed on one line, separated by spaces.
+    pub expressions: Vec<Box<dyn Expression>>,
     pub line: u32,
 }
 impl Statement for PrintStatement {}
@@ -19,8 +20,10 @@ impl Statement for ExpressionStatement {}
 
 #[derive(Debug)]
 pub struct VarStatement {
-    pub name: String,
+    pub name: Symbol,
     pub initializer: Option<Box<dyn Expression>>,
+    /// Declared with `const`, the variable can't be assigned to afterwards.
+    pub is_const: bool,
     pub line: u32,
 }
 impl Statement for VarStatement {}
@@ -39,24 +42,62 @@ pub struct IfStatement {
 }
 impl Statement for IfStatement {}
 
+/// `for (name in iterable) body`, looping over the elements of a list, the keys of a map or
+/// the characters of a string.
+#[derive(Debug)]
+pub struct ForEachStatement {
+    pub name: Symbol,
+    pub iterable: Box<dyn Expression>,
+    pub body: Box<dyn Statement>,
+    pub line: u32,
+}
+impl Statement for ForEachStatement {}
+
 #[derive(Debug)]
 pub struct WhileStatement {
     pub condition: Box<dyn Expression>,
     pub body: Box<dyn Statement>,
+    /// Runs after every iteration, including ones ended by `continue`.
+    pub maybe_increment: Option<Box<dyn Expression>>,
+    pub line: u32,
 }
 impl Statement for WhileStatement {}
 
+#[derive(Debug)]
+pub struct DoWhileStatement {
+    pub body: Box<dyn Statement>,
+    pub condition: Box<dyn Expression>,
+    pub line: u32,
+}
+impl Statement for DoWhileStatement {}
+
+#[derive(Debug)]
+pub struct BreakStatement {
+    pub line: u32,
+}
+impl Statement for BreakStatement {}
+
+#[derive(Debug)]
+pub struct ContinueStatement {
+    pub line: u32,
+}
+impl Statement for ContinueStatement {}
+
 #[derive(Debug)]
 pub struct Parameter {
-    pub name: String,
+    pub name: Symbol,
+    /// Written as `...name`, collects the remaining arguments in a list. Only the last
+    /// parameter can be one.
+    pub is_rest: bool,
     pub line: u32,
 }
 
 #[derive(Debug)]
 pub struct FunctionStatement {
-    pub name: String,
+    pub name: Symbol,
     pub parameters: Vec<Parameter>,
     pub statements: Rc<Vec<Box<dyn Statement>>>,
+    pub is_getter: bool,
     pub line: u32,
 }
 impl Statement for FunctionStatement {}
@@ -64,14 +105,18 @@ impl Statement for FunctionStatement {}
 #[derive(Debug)]
 pub struct ReturnStatement {
     pub maybe_expression: Option<Box<dyn Expression>>,
+    /// Set by the resolver when the returned expression is a call.
+    pub is_tail_call: bool,
     pub line: u32,
 }
 impl Statement for ReturnStatement {}
 
 #[derive(Debug)]
 pub struct ClassStatement {
-    pub name: String,
-    pub methods: Rc<HashMap<String, FunctionStatement>>,
+    pub name: Symbol,
+    /// In the order they are declared in; of methods with the same name, the last one wins.
+    pub methods: Rc<Vec<FunctionStatement>>,
+    pub static_methods: Rc<Vec<FunctionStatement>>,
     pub maybe_superclass: Option<VariableExpression>,
     pub line: u32,
 }
@@ -1,61 +1,187 @@
 use serde::Serialize;
-use std::{borrow::Cow, fmt::Display};
+use std::{
+    borrow::Cow,
+    fmt::{Display, Write},
+};
 use thiserror::Error;
 
 #[derive(Error, Debug, Serialize)]
 pub enum Error {
     ScannerErrors(Vec<ErrorDetail>),
     SyntaxErrors(Vec<ErrorDetail>),
+    /// The only syntax error is that the source ends in the middle of a statement, so a REPL
+    /// can read another line instead of reporting it.
+    IncompleteInput(ErrorDetail),
     ResolverErrors(Vec<ErrorDetail>),
     RuntimeError(ErrorDetail),
+    /// Runtime errors of all top-level statements when the interpreter keeps going after one.
+    RuntimeErrors(Vec<ErrorDetail>),
+    /// Raised by the `exit` native to unwind to the top level, which ends the process.
+    Exit(i32),
 }
 
-impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Error {
+    /// Native functions don't know where they were called from, so they raise runtime errors
+    /// with line 0 and the call expression fills in its own line.
+    pub fn with_call_line(self, line: u32) -> Self {
         match self {
-            Error::ScannerErrors(errors) => {
-                writeln!(f, "Scanner error(s):")?;
-                for error in errors {
-                    writeln!(f, "{error}")?;
-                }
+            Error::RuntimeError(mut detail) if detail.line == 0 => {
+                detail.line = line;
+                Error::RuntimeError(detail)
             }
-            Error::SyntaxErrors(errors) => {
-                writeln!(f, "Syntax error(s):")?;
-                for error in errors {
-                    writeln!(f, "{error}")?;
-                }
+            error => error,
+        }
+    }
+
+    /// Attaches the call stack to a runtime error, unless a deeper call already did.
+    pub fn with_trace(self, trace: impl FnOnce() -> Vec<String>) -> Self {
+        match self {
+            Error::RuntimeError(mut detail) if detail.trace.is_empty() => {
+                detail.trace = trace();
+                Error::RuntimeError(detail)
             }
-            Error::RuntimeError(detail) => {
-                writeln!(f, "Runtime error: {detail}")?;
+            error => error,
+        }
+    }
+}
+
+impl Error {
+    /// Formats the error like `Display` does, but also shows the source line of each error
+    /// whose column is known, with a caret under the column.
+    pub fn report(&self, source: &str) -> String {
+        let mut report = String::new();
+        self.write_to(&mut report, Some(source))
+            .expect("writing to a String can't fail");
+        report
+    }
+
+    fn write_to<W: Write>(&self, w: &mut W, maybe_source: Option<&str>) -> std::fmt::Result {
+        let write_all = |w: &mut W, header: &str, errors: &[ErrorDetail]| {
+            writeln!(w, "{header}")?;
+            for error in errors {
+                error.write_to(w, maybe_source)?;
+                writeln!(w)?;
+            }
+            Ok(())
+        };
+        match self {
+            Error::ScannerErrors(errors) => write_all(w, "Scanner error(s):", errors),
+            Error::SyntaxErrors(errors) => write_all(w, "Syntax error(s):", errors),
+            Error::IncompleteInput(detail) => {
+                write_all(w, "Syntax error(s):", std::slice::from_ref(detail))
             }
-            Error::ResolverErrors(errors) => {
-                writeln!(f, "Resolver error(s):")?;
-                for error in errors {
-                    writeln!(f, "{error}")?;
-                }
+            Error::RuntimeError(detail) => {
+                write!(w, "Runtime error: ")?;
+                detail.write_to(w, maybe_source)?;
+                writeln!(w)
             }
+            Error::RuntimeErrors(errors) => write_all(w, "Runtime error(s):", errors),
+            Error::Exit(code) => writeln!(w, "Exited with code {code}."),
+            Error::ResolverErrors(errors) => write_all(w, "Resolver error(s):", errors),
         }
-        Ok(())
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_to(f, None)
     }
 }
 
 #[derive(Debug, Serialize)]
 pub struct ErrorDetail {
     line: u32,
+    column: u32,
     message: Cow<'static, str>,
+    /// Calls that were active when a runtime error happened, innermost first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    trace: Vec<String>,
 }
 
 impl ErrorDetail {
     pub fn new(line: u32, message: impl Into<Cow<'static, str>>) -> Self {
+        Self::new_with_col(line, 0, message)
+    }
+
+    /// Columns are 1-based, 0 means the column is unknown.
+    pub fn new_with_col(line: u32, column: u32, message: impl Into<Cow<'static, str>>) -> Self {
         Self {
-            line: line,
+            line,
+            column,
             message: message.into(),
+            trace: vec![],
+        }
+    }
+}
+
+impl ErrorDetail {
+    fn write_to(&self, w: &mut impl Write, maybe_source: Option<&str>) -> std::fmt::Result {
+        if self.column > 0 {
+            write!(
+                w,
+                "[ line {}, col {} ] : {}",
+                self.line, self.column, self.message
+            )?;
+        } else {
+            write!(w, "[ line {} ] : {}", self.line, self.message)?;
+        }
+        if let Some(source_line) = maybe_source.and_then(|source| self.source_line(source)) {
+            // tabs are kept so the caret lines up however wide they are shown
+            let indent: String = source_line
+                .chars()
+                .chain(std::iter::repeat(' '))
+                .take(self.column as usize - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let gutter = " ".repeat(self.line.to_string().len());
+            write!(w, "\n{gutter} |")?;
+            write!(w, "\n{} | {source_line}", self.line)?;
+            write!(w, "\n{gutter} | {indent}^")?;
         }
+        for frame in &self.trace {
+            write!(w, "\n    {frame}")?;
+        }
+        Ok(())
+    }
+
+    /// The line the error is in, `None` if the column isn't known.
+    fn source_line<'s>(&self, source: &'s str) -> Option<&'s str> {
+        if self.column == 0 || self.line == 0 {
+            return None;
+        }
+        source.lines().nth(self.line as usize - 1)
     }
 }
 
 impl Display for ErrorDetail {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[ line {} ] : {}", self.line, self.message)
+        self.write_to(f, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let source = "var a = 1;\nprint a +;\n";
+        let error = Error::SyntaxErrors(vec![ErrorDetail::new_with_col(
+            2,
+            10,
+            "Expect expression, found ';'.",
+        )]);
+        assert_eq!(
+            error.report(source),
+            "Syntax error(s):\n\
+             [ line 2, col 10 ] : Expect expression, found ';'.\n  |\n\
+             2 | print a +;\n  |          ^\n"
+        );
+
+        // without a column, or a line that isn't in the source, it's the compact form
+        let error = Error::RuntimeError(ErrorDetail::new(1, "Undefined variable 'b'."));
+        assert_eq!(error.report(source), error.to_string());
+        let error = Error::SyntaxErrors(vec![ErrorDetail::new_with_col(9, 1, "Missing.")]);
+        assert_eq!(error.report(source), error.to_string());
     }
 }
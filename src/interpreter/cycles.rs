@@ -0,0 +1,278 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    rc::{Rc, Weak},
+};
+
+use crate::loxtype::{LoxCallable, LoxClass, LoxInstance, LoxMap, LoxType, MapKey};
+
+use super::{Context, Environment};
+
+/// Fewest instances tracked before a collection runs.
+pub(super) const MIN_THRESHOLD: usize = 1024;
+
+/// Instances are reference counted, so one that ends up referencing itself, like after
+/// `a.self = a;`, is never freed. Every instance is registered here when it is created, and
+/// once twice as many are tracked as after the last collection, `collect` frees the ones only
+/// kept alive by such cycles by clearing their fields.
+#[derive(Debug)]
+pub struct Instances {
+    tracked: Vec<Weak<RefCell<LoxInstance>>>,
+    /// Length of `tracked` at which the next collection runs.
+    threshold: usize,
+}
+
+impl Default for Instances {
+    fn default() -> Self {
+        Self {
+            tracked: vec![],
+            threshold: MIN_THRESHOLD,
+        }
+    }
+}
+
+impl Instances {
+    /// Returns true when it's time to collect. Besides freeing cycles, that drops the
+    /// entries of freed instances, whose `Weak`s keep their allocations around.
+    pub fn track(&mut self, instance: &Rc<RefCell<LoxInstance>>) -> bool {
+        self.tracked.push(Rc::downgrade(instance));
+        self.tracked.len() >= self.threshold
+    }
+
+    fn prune(&mut self) {
+        self.tracked.retain(|weak| weak.strong_count() > 0);
+        self.threshold = MIN_THRESHOLD.max(self.tracked.len() * 2);
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.tracked.len()
+    }
+}
+
+/// A reference counted value reached while walking from the tracked instances.
+#[derive(Clone)]
+enum Handle {
+    Callable(Rc<dyn LoxCallable>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    List(Rc<RefCell<Vec<LoxType>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    Env(Rc<RefCell<Environment>>),
+}
+
+struct Node {
+    /// Strong count when the value was first reached, not counting the handle.
+    strong: usize,
+    /// How many of those references come from other nodes.
+    internal: usize,
+    children: Vec<*const ()>,
+    handle: Handle,
+}
+
+/// Walks everything reachable from the tracked instances and counts the references between
+/// the values it finds. A value with more references than that is also referenced from
+/// somewhere else, like a variable, the Rust stack or a host, and is alive.
+#[derive(Default)]
+pub struct Marker {
+    nodes: HashMap<*const (), Node>,
+    /// Values reached but not walked yet.
+    pending: Vec<*const ()>,
+    /// The value whose references are being walked.
+    parent: Option<*const ()>,
+}
+
+impl Marker {
+    fn reach(&mut self, ptr: *const (), strong: usize, handle: impl FnOnce() -> Handle) {
+        if let Entry::Vacant(entry) = self.nodes.entry(ptr) {
+            entry.insert(Node {
+                strong,
+                internal: 0,
+                children: vec![],
+                handle: handle(),
+            });
+            self.pending.push(ptr);
+        }
+        if let Some(parent) = self.parent {
+            self.nodes.get_mut(&ptr).unwrap().internal += 1;
+            self.nodes.get_mut(&parent).unwrap().children.push(ptr);
+        }
+    }
+
+    pub fn mark_value(&mut self, value: &LoxType) {
+        match value {
+            LoxType::Number(_) | LoxType::Boolean(_) | LoxType::String(_) | LoxType::Nil => (),
+            LoxType::Callable(callable) => self.mark_callable(callable),
+            LoxType::Class(class) => self.mark_class(class),
+            LoxType::Instance(instance) => self.reach(
+                Rc::as_ptr(instance).cast(),
+                Rc::strong_count(instance),
+                || Handle::Instance(instance.clone()),
+            ),
+            LoxType::List(list) => self.mark_list(list),
+            LoxType::Map(map) => self.reach(Rc::as_ptr(map).cast(), Rc::strong_count(map), || {
+                Handle::Map(map.clone())
+            }),
+        }
+    }
+
+    fn mark_callable(&mut self, callable: &Rc<dyn LoxCallable>) {
+        self.reach(
+            Rc::as_ptr(callable).cast(),
+            Rc::strong_count(callable),
+            || Handle::Callable(callable.clone()),
+        );
+    }
+
+    pub fn mark_class(&mut self, class: &Rc<LoxClass>) {
+        self.reach(Rc::as_ptr(class).cast(), Rc::strong_count(class), || {
+            Handle::Class(class.clone())
+        });
+    }
+
+    pub fn mark_list(&mut self, list: &Rc<RefCell<Vec<LoxType>>>) {
+        self.reach(Rc::as_ptr(list).cast(), Rc::strong_count(list), || {
+            Handle::List(list.clone())
+        });
+    }
+
+    fn mark_key(&mut self, key: &MapKey) {
+        match key {
+            MapKey::Number(_) | MapKey::String(_) => (),
+            MapKey::Callable(callable) => self.mark_callable(callable),
+            MapKey::Class(class) => self.mark_class(class),
+        }
+    }
+
+    pub fn mark_ctx(&mut self, ctx: &Context) {
+        self.mark_env(&ctx.env);
+    }
+
+    fn mark_env(&mut self, env: &Rc<RefCell<Environment>>) {
+        self.reach(Rc::as_ptr(env).cast(), Rc::strong_count(env), || {
+            Handle::Env(env.clone())
+        });
+    }
+
+    /// Walks the references of everything reached so far, in a loop so long chains don't
+    /// overflow the stack. Values borrowed right now are skipped, which only makes what they
+    /// reference look alive.
+    fn walk(&mut self) {
+        while let Some(ptr) = self.pending.pop() {
+            let handle = self.nodes[&ptr].handle.clone();
+            self.parent = Some(ptr);
+            match &handle {
+                Handle::Callable(callable) => callable.trace(self),
+                Handle::Class(class) => class.trace(self),
+                Handle::Instance(instance) => {
+                    if let Ok(instance) = instance.try_borrow() {
+                        instance.trace(self);
+                    }
+                }
+                Handle::List(list) => {
+                    if let Ok(list) = list.try_borrow() {
+                        for element in list.iter() {
+                            self.mark_value(element);
+                        }
+                    }
+                }
+                Handle::Map(map) => {
+                    if let Ok(map) = map.try_borrow() {
+                        for key in map.keys() {
+                            self.mark_key(key);
+                        }
+                        for value in map.values() {
+                            self.mark_value(value);
+                        }
+                    }
+                }
+                Handle::Env(env) => {
+                    if let Ok(env) = env.try_borrow() {
+                        for value in env.values() {
+                            self.mark_value(value);
+                        }
+                        if let Some(enclosing) = env.enclosing() {
+                            self.mark_env(enclosing);
+                        }
+                    }
+                }
+            }
+            self.parent = None;
+        }
+    }
+
+    /// Nodes referenced from outside of the walked values, and everything they reference.
+    fn alive(&self) -> HashSet<*const ()> {
+        let mut alive = HashSet::new();
+        let mut stack: Vec<_> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.strong > node.internal)
+            .map(|(ptr, _)| *ptr)
+            .collect();
+        while let Some(ptr) = stack.pop() {
+            if alive.insert(ptr) {
+                stack.extend(&self.nodes[&ptr].children);
+            }
+        }
+        alive
+    }
+}
+
+/// Frees the instances that are only referenced from values that are unreachable themselves,
+/// which can only happen through a cycle. Anything else holding a reference, a variable, a
+/// running call or a host, keeps them alive, so this is safe to run at any time.
+pub fn collect(ctx: &Context) {
+    let mut instances = ctx.instances.borrow_mut();
+    instances.prune();
+
+    let mut marker = Marker::default();
+    for weak in &instances.tracked {
+        if let Some(instance) = weak.upgrade() {
+            let strong = Rc::strong_count(&instance) - 1;
+            marker.reach(Rc::as_ptr(&instance).cast(), strong, || {
+                Handle::Instance(instance)
+            });
+        }
+    }
+    drop(instances);
+    marker.walk();
+
+    // everything unreachable is emptied, which breaks the cycles. The values taken out are
+    // only dropped at the end, the handles keep the nodes alive while they are borrowed
+    let alive = marker.alive();
+    let mut unreachable = vec![];
+    for (ptr, node) in &marker.nodes {
+        if alive.contains(ptr) {
+            continue;
+        }
+        match &node.handle {
+            Handle::Instance(instance) => {
+                if let Ok(mut instance) = instance.try_borrow_mut() {
+                    unreachable.extend(instance.take_fields().into_values());
+                }
+            }
+            Handle::List(list) => {
+                if let Ok(mut list) = list.try_borrow_mut() {
+                    unreachable.append(&mut list);
+                }
+            }
+            Handle::Map(map) => {
+                if let Ok(mut map) = map.try_borrow_mut() {
+                    unreachable.extend(map.values().cloned());
+                    *map = LoxMap::default();
+                }
+            }
+            Handle::Env(env) => {
+                if let Ok(mut env) = env.try_borrow_mut() {
+                    unreachable.extend(env.values().cloned());
+                    env.clear();
+                }
+            }
+            Handle::Callable(_) | Handle::Class(_) => (),
+        }
+    }
+    drop(marker);
+    drop(unreachable);
+    ctx.instances.borrow_mut().prune();
+}
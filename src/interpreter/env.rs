@@ -1,70 +1,168 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::loxtype::LoxType;
+use crate::{loxtype::LoxType, symbol::Symbol};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct UndefinedVariable();
 
+/// Why assigning to a variable failed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AssignError {
+    Undefined,
+    /// The variable is a global declared with `const`.
+    Constant,
+}
+
+impl From<UndefinedVariable> for AssignError {
+    fn from(_: UndefinedVariable) -> Self {
+        AssignError::Undefined
+    }
+}
+
+/// A global and whether it was declared with `const`. The resolver only sees one program at
+/// a time, so assignments from later programs, like the next line at the prompt, or from
+/// functions declared before the constant are checked when they run.
+#[derive(Debug, Clone)]
+pub struct Global {
+    value: LoxType,
+    is_const: bool,
+}
+
+/// Variables of one scope. Locals live in slots handed out by the resolver, only the global
+/// environment looks its variables up by name.
 #[derive(Debug)]
 pub struct Environment {
     maybe_enclosing: Option<Rc<RefCell<Environment>>>,
-    values: HashMap<String, LoxType>,
+    slots: Vec<LoxType>,
+    values: HashMap<Symbol, Global>,
 }
 
 impl Environment {
     pub fn new(maybe_enclosing: Option<Rc<RefCell<Environment>>>) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Self {
             maybe_enclosing,
+            slots: vec![],
             values: HashMap::new(),
         }))
     }
 
-    pub fn define(&mut self, name: &str, value: LoxType) {
-        self.values.insert(name.to_owned(), value);
+    /// Locals must be defined in the order the resolver declared them, so each ends up in
+    /// its slot.
+    pub fn define(&mut self, name: &Symbol, value: LoxType) {
+        self.define_global_or_local(name, value, false);
+    }
+
+    /// Like `define`, but a global defined this way can't be assigned to. Constant locals
+    /// are all checked by the resolver.
+    pub fn define_const(&mut self, name: &Symbol, value: LoxType) {
+        self.define_global_or_local(name, value, true);
+    }
+
+    fn define_global_or_local(&mut self, name: &Symbol, value: LoxType, is_const: bool) {
+        if self.maybe_enclosing.is_some() {
+            self.slots.push(value);
+        } else {
+            self.values.insert(name.clone(), Global { value, is_const });
+        }
+    }
+
+    /// Everything defined in this scope, locals and globals.
+    pub fn values(&self) -> impl Iterator<Item = &LoxType> {
+        self.slots
+            .iter()
+            .chain(self.values.values().map(|global| &global.value))
+    }
+
+    /// Globals in the order of their names.
+    pub fn globals(&self) -> Vec<(&Symbol, &LoxType)> {
+        let mut globals: Vec<_> = self
+            .values
+            .iter()
+            .map(|(name, global)| (name, &global.value))
+            .collect();
+        globals.sort_by(|(a, _), (b, _)| str::cmp(a, b));
+        globals
+    }
+
+    /// Drops all variables, breaking cycles between them and closures defined in this scope.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.values.clear();
+    }
+
+    /// A copy of the global variables, to put them back with `restore_globals`. Values are
+    /// shared, so changes to lists or instances aren't undone.
+    pub fn snapshot_globals(&self) -> HashMap<Symbol, Global> {
+        self.values.clone()
+    }
+
+    pub fn restore_globals(&mut self, snapshot: HashMap<Symbol, Global>) {
+        self.values = snapshot;
+    }
+
+    pub fn enclosing(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.maybe_enclosing.as_ref()
+    }
+
+    pub fn assign_global(&mut self, name: &Symbol, value: LoxType) -> Result<(), AssignError> {
+        match self.values.get_mut(name) {
+            Some(global) if global.is_const => Err(AssignError::Constant),
+            Some(global) => {
+                global.value = value;
+                Ok(())
+            }
+            None => Err(AssignError::Undefined),
+        }
+    }
+
+    pub fn get_global(&self, name: &Symbol) -> Result<LoxType, UndefinedVariable> {
+        self.values
+            .get(name)
+            .map(|global| global.value.clone())
+            .ok_or(UndefinedVariable())
     }
 
     pub fn assign_at(
         &mut self,
         distance: u32,
-        name: &str,
+        slot: usize,
         value: LoxType,
     ) -> Result<(), UndefinedVariable> {
-        if distance == 0 {
-            if self.values.contains_key(name) {
-                self.values.insert(name.to_owned(), value);
+        let assign = |slots: &mut Vec<LoxType>| match slots.get_mut(slot) {
+            Some(v) => {
+                *v = value;
                 Ok(())
-            } else {
-                Err(UndefinedVariable())
             }
+            None => Err(UndefinedVariable()),
+        };
+        if distance == 0 {
+            assign(&mut self.slots)
         } else {
-            if let Some(enclosing) = &self.maybe_enclosing {
-                enclosing.borrow_mut().assign_at(distance - 1, name, value)
-            } else {
-                panic!(
-                    "line {}: could not assign variable {} at distance {}",
-                    distance, name, distance
-                )
-            }
+            assign(&mut self.ancestor(distance)?.borrow_mut().slots)
         }
     }
 
-    pub fn get_at(&self, distance: u32, name: &str) -> Result<LoxType, UndefinedVariable> {
+    pub fn get_at(&self, distance: u32, slot: usize) -> Result<LoxType, UndefinedVariable> {
+        let get = |slots: &Vec<LoxType>| slots.get(slot).cloned().ok_or(UndefinedVariable());
         if distance == 0 {
-            self.values
-                .get(name)
-                .map(|v| v.clone())
-                .ok_or(UndefinedVariable())
+            get(&self.slots)
         } else {
-            if let Some(enclosing) = &self.maybe_enclosing {
-                enclosing.borrow().get_at(distance - 1, name)
-            } else {
-                panic!(
-                    "line {}: could not read variable {} at distance {}",
-                    33, name, distance
-                )
-            }
+            get(&self.ancestor(distance)?.borrow().slots)
         }
     }
+
+    /// The scope `distance` levels up, at least one. Walks the chain in a loop, so deeply
+    /// nested scopes don't hold a borrow of every level at once. A distance past the globals
+    /// means the resolver and the interpreter disagree, which is reported like an undefined
+    /// variable instead of crashing.
+    fn ancestor(&self, distance: u32) -> Result<Rc<RefCell<Environment>>, UndefinedVariable> {
+        let mut env = self.maybe_enclosing.clone().ok_or(UndefinedVariable())?;
+        for _ in 1..distance {
+            let enclosing = env.borrow().maybe_enclosing.clone();
+            env = enclosing.ok_or(UndefinedVariable())?;
+        }
+        Ok(env)
+    }
 }
 
 #[cfg(test)]
@@ -73,8 +171,12 @@ mod tests {
 
     fn test_env() -> Rc<RefCell<Environment>> {
         let global = Environment::new(None);
-        global.borrow_mut().define("a", LoxType::Number(1.0));
+        global
+            .borrow_mut()
+            .define(&Symbol::intern("a"), LoxType::Number(1.0));
         let e1 = Environment::new(Some(global));
+        e1.borrow_mut()
+            .define(&Symbol::intern("b"), LoxType::Number(2.0));
         let e2 = Environment::new(Some(e1));
         e2
     }
@@ -82,14 +184,14 @@ mod tests {
     #[test]
     fn test_get() {
         let env = test_env();
-        let n = env.borrow().get_at(2, "a").unwrap();
-        assert_eq!(n, LoxType::Number(1.0));
+        let n = env.borrow().get_at(1, 0).unwrap();
+        assert_eq!(n, LoxType::Number(2.0));
     }
 
     #[test]
     fn test_get_undefined() {
         let env = test_env();
-        let e = env.borrow().get_at(2, "c").unwrap_err();
+        let e = env.borrow().get_at(1, 1).unwrap_err();
         assert_eq!(e, UndefinedVariable());
     }
 
@@ -97,9 +199,9 @@ mod tests {
     fn test_assign() {
         let env = test_env();
         env.borrow_mut()
-            .assign_at(2, "a", LoxType::Boolean(false))
+            .assign_at(1, 0, LoxType::Boolean(false))
             .unwrap();
-        let v = env.borrow().get_at(2, "a").unwrap();
+        let v = env.borrow().get_at(1, 0).unwrap();
         assert_eq!(v, LoxType::Boolean(false));
     }
 
@@ -108,16 +210,102 @@ mod tests {
         let env = test_env();
         let e = env
             .borrow_mut()
-            .assign_at(2, "c", LoxType::Boolean(false))
+            .assign_at(1, 1, LoxType::Boolean(false))
             .unwrap_err();
         assert_eq!(e, UndefinedVariable());
     }
 
+    #[test]
+    fn test_distance_past_globals() {
+        let env = test_env();
+        assert_eq!(env.borrow().get_at(3, 0), Err(UndefinedVariable()));
+        assert_eq!(
+            env.borrow_mut().assign_at(3, 0, LoxType::Nil),
+            Err(UndefinedVariable())
+        );
+    }
+
+    #[test]
+    fn test_deep_scopes() {
+        const DEPTH: u32 = 100_000;
+        let global = Environment::new(None);
+        let mut env = Environment::new(Some(global));
+        env.borrow_mut()
+            .define(&Symbol::intern("outermost"), LoxType::Number(0.0));
+        for _ in 0..DEPTH {
+            env = Environment::new(Some(env));
+        }
+        env.borrow_mut()
+            .assign_at(DEPTH, 0, LoxType::Boolean(true))
+            .unwrap();
+        assert_eq!(
+            env.borrow().get_at(DEPTH, 0).unwrap(),
+            LoxType::Boolean(true)
+        );
+
+        // dropping the chain in one go would recurse once per scope
+        let mut maybe_env = Some(env);
+        while let Some(env) = maybe_env {
+            maybe_env = env.borrow_mut().maybe_enclosing.take();
+        }
+    }
+
     #[test]
     fn test_define() {
         let env = test_env();
-        env.borrow_mut().define("foo", LoxType::Nil);
-        let v = env.borrow().get_at(0, "foo").unwrap();
-        assert_eq!(v, LoxType::Nil);
+        env.borrow_mut()
+            .define(&Symbol::intern("foo"), LoxType::Nil);
+        env.borrow_mut()
+            .define(&Symbol::intern("bar"), LoxType::Boolean(true));
+        let v = env.borrow().get_at(0, 1).unwrap();
+        assert_eq!(v, LoxType::Boolean(true));
+    }
+
+    #[test]
+    fn test_globals() {
+        let global = Environment::new(None);
+        global
+            .borrow_mut()
+            .define(&Symbol::intern("a"), LoxType::Number(1.0));
+        global
+            .borrow_mut()
+            .assign_global(&Symbol::intern("a"), LoxType::Nil)
+            .unwrap();
+        assert_eq!(
+            global.borrow().get_global(&Symbol::intern("a")).unwrap(),
+            LoxType::Nil
+        );
+        assert_eq!(
+            global
+                .borrow_mut()
+                .assign_global(&Symbol::intern("b"), LoxType::Nil),
+            Err(AssignError::Undefined)
+        );
+        assert_eq!(
+            global.borrow().get_global(&Symbol::intern("b")),
+            Err(UndefinedVariable())
+        );
+    }
+
+    #[test]
+    fn test_const_globals() {
+        let global = Environment::new(None);
+        global
+            .borrow_mut()
+            .define_const(&Symbol::intern("a"), LoxType::Number(1.0));
+        assert_eq!(
+            global
+                .borrow_mut()
+                .assign_global(&Symbol::intern("a"), LoxType::Nil),
+            Err(AssignError::Constant)
+        );
+        // redeclaring it with `var` makes it assignable again
+        global
+            .borrow_mut()
+            .define(&Symbol::intern("a"), LoxType::Number(2.0));
+        global
+            .borrow_mut()
+            .assign_global(&Symbol::intern("a"), LoxType::Nil)
+            .unwrap();
     }
 }
@@ -1,13 +1,18 @@
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
 use crate::{
     ast::*,
     error::{Error, ErrorDetail},
-    loxtype::{LoxInstance, LoxType},
+    loxtype::{LoxCallable, LoxFunction, LoxInstance, LoxMap, LoxType, MapKey},
+    native_fns::ListMethod,
+    symbol::Symbol,
     Result,
 };
 
-use super::{Context, Eval};
+use super::{AssignError, Context, Eval};
+
+/// Longest string, in bytes, that repeating a string with `*` may produce.
+const MAX_REPEAT_LEN: usize = 1 << 30;
 
 impl Eval for NilExpression {
     fn eval(&self, _: Context) -> Result<LoxType> {
@@ -40,6 +45,18 @@ impl Eval for NotExpression {
     }
 }
 
+impl Eval for BitNotExpression {
+    fn eval(&self, ctx: Context) -> Result<LoxType> {
+        match as_integer(&self.expression.eval(ctx)?) {
+            Some(n) => Ok(LoxType::Number(!n as f64)),
+            None => Err(Error::RuntimeError(ErrorDetail::new(
+                self.line,
+                "Operand must be an integer.",
+            ))),
+        }
+    }
+}
+
 impl Eval for GroupingExpression {
     fn eval(&self, ctx: Context) -> Result<LoxType> {
         self.0.eval(ctx)
@@ -50,71 +67,182 @@ impl Eval for BinaryExpression {
     fn eval(&self, ctx: Context) -> Result<LoxType> {
         let left = self.left.eval(ctx.clone())?;
         let right = self.right.eval(ctx)?;
+        binary_operation(self.operator, left, right, self.line)
+    }
+}
 
-        let incompatible_operands = Err(Error::RuntimeError(ErrorDetail::new(
-            self.line,
-            "Incompatible operands.",
+/// Bitwise operators work on numbers without a fractional part that fit in an `i64`.
+fn as_integer(value: &LoxType) -> Option<i64> {
+    match value {
+        LoxType::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Some(*n as i64),
+        _ => None,
+    }
+}
+
+fn bitwise_operation(
+    left: &LoxType,
+    right: &LoxType,
+    line: u32,
+    operation: fn(i64, i64) -> i64,
+) -> Result<LoxType> {
+    match (as_integer(left), as_integer(right)) {
+        (Some(l), Some(r)) => Ok(LoxType::Number(operation(l, r) as f64)),
+        _ => Err(Error::RuntimeError(ErrorDetail::new(
+            line,
+            "Operands must be integers.",
+        ))),
+    }
+}
+
+/// Shifting an `i64` by 64 bits or more isn't defined, so those amounts are an error like
+/// negative ones.
+fn shift_operation(
+    operator: BinaryOperator,
+    left: &LoxType,
+    right: &LoxType,
+    line: u32,
+) -> Result<LoxType> {
+    let (Some(l), Some(r)) = (as_integer(left), as_integer(right)) else {
+        return Err(Error::RuntimeError(ErrorDetail::new(
+            line,
+            "Operands must be integers.",
         )));
-        let r = match self.operator {
-            BinaryOperator::Add => match (left, right) {
-                (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l + r),
-                (LoxType::String(l), LoxType::String(r)) => LoxType::String(format!("{}{}", l, r)),
-                _ => {
-                    return incompatible_operands;
-                }
-            },
-            BinaryOperator::Substract => match (left, right) {
-                (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l - r),
-                _ => {
-                    return incompatible_operands;
-                }
-            },
-            BinaryOperator::Multiply => match (left, right) {
-                (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l * r),
-                _ => {
-                    return incompatible_operands;
-                }
-            },
-            BinaryOperator::Divide => match (left, right) {
-                (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l / r),
-                _ => {
-                    return incompatible_operands;
-                }
-            },
-            BinaryOperator::Equal => LoxType::Boolean(left == right),
-            BinaryOperator::NotEqual => LoxType::Boolean(left != right),
-            BinaryOperator::Less => match (left, right) {
-                (LoxType::Number(l), LoxType::Number(r)) => LoxType::Boolean(l < r),
-                _ => {
-                    return incompatible_operands;
-                }
-            },
-            BinaryOperator::LessOrEqual => match (left, right) {
-                (LoxType::Number(l), LoxType::Number(r)) => LoxType::Boolean(l <= r),
-                _ => {
-                    return incompatible_operands;
-                }
-            },
-            BinaryOperator::Greater => match (left, right) {
-                (LoxType::Number(l), LoxType::Number(r)) => LoxType::Boolean(l > r),
-                _ => {
-                    return incompatible_operands;
+    };
+    if !(0..64).contains(&r) {
+        return Err(Error::RuntimeError(ErrorDetail::new(
+            line,
+            "Shift amount must be between 0 and 63.",
+        )));
+    }
+    let shifted = match operator {
+        BinaryOperator::ShiftLeft => l << r,
+        BinaryOperator::ShiftRight => l >> r,
+        _ => unreachable!(),
+    };
+    Ok(LoxType::Number(shifted as f64))
+}
+
+fn binary_operation(
+    operator: BinaryOperator,
+    left: LoxType,
+    right: LoxType,
+    line: u32,
+) -> Result<LoxType> {
+    let incompatible_operands = Err(Error::RuntimeError(ErrorDetail::new(
+        line,
+        "Incompatible operands.",
+    )));
+    let division_by_zero = Err(Error::RuntimeError(ErrorDetail::new(
+        line,
+        "Division by zero.",
+    )));
+    let r = match operator {
+        BinaryOperator::Add => match (left, right) {
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l + r),
+            (LoxType::String(l), r) => LoxType::String(format!("{}{}", l, r)),
+            (l, LoxType::String(r)) => LoxType::String(format!("{}{}", l, r)),
+            _ => {
+                return incompatible_operands;
+            }
+        },
+        BinaryOperator::Concat => LoxType::String(format!("{left}{right}")),
+        BinaryOperator::Substract => match (left, right) {
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l - r),
+            _ => {
+                return incompatible_operands;
+            }
+        },
+        BinaryOperator::Multiply => match (left, right) {
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l * r),
+            (LoxType::String(s), LoxType::Number(n)) | (LoxType::Number(n), LoxType::String(s)) => {
+                if n < 0.0 || n.fract() != 0.0 {
+                    return Err(Error::RuntimeError(ErrorDetail::new(
+                        line,
+                        "String repeat count must be a non-negative integer.",
+                    )));
                 }
-            },
-            BinaryOperator::GreaterOrEqual => match (left, right) {
-                (LoxType::Number(l), LoxType::Number(r)) => LoxType::Boolean(l >= r),
-                _ => {
-                    return incompatible_operands;
+                if s.len()
+                    .checked_mul(n as usize)
+                    .is_none_or(|len| len > MAX_REPEAT_LEN)
+                {
+                    return Err(Error::RuntimeError(ErrorDetail::new(
+                        line,
+                        "Repeat count too large.",
+                    )));
                 }
-            },
-        };
-        Ok(r)
-    }
+                LoxType::String(s.repeat(n as usize))
+            }
+            _ => {
+                return incompatible_operands;
+            }
+        },
+        BinaryOperator::Divide => match (left, right) {
+            (LoxType::Number(_), LoxType::Number(0.0)) => {
+                return division_by_zero;
+            }
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l / r),
+            _ => {
+                return incompatible_operands;
+            }
+        },
+        BinaryOperator::Modulo => match (left, right) {
+            (LoxType::Number(_), LoxType::Number(0.0)) => {
+                return division_by_zero;
+            }
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l % r),
+            _ => {
+                return incompatible_operands;
+            }
+        },
+        BinaryOperator::Power => match (left, right) {
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Number(l.powf(r)),
+            _ => {
+                return incompatible_operands;
+            }
+        },
+        BinaryOperator::BitAnd => return bitwise_operation(&left, &right, line, |l, r| l & r),
+        BinaryOperator::BitOr => return bitwise_operation(&left, &right, line, |l, r| l | r),
+        BinaryOperator::BitXor => return bitwise_operation(&left, &right, line, |l, r| l ^ r),
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+            return shift_operation(operator, &left, &right, line)
+        }
+        BinaryOperator::Equal => LoxType::Boolean(left == right),
+        BinaryOperator::NotEqual => LoxType::Boolean(left != right),
+        BinaryOperator::Less => match (left, right) {
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Boolean(l < r),
+            (LoxType::String(l), LoxType::String(r)) => LoxType::Boolean(l < r),
+            _ => {
+                return incompatible_operands;
+            }
+        },
+        BinaryOperator::LessOrEqual => match (left, right) {
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Boolean(l <= r),
+            (LoxType::String(l), LoxType::String(r)) => LoxType::Boolean(l <= r),
+            _ => {
+                return incompatible_operands;
+            }
+        },
+        BinaryOperator::Greater => match (left, right) {
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Boolean(l > r),
+            (LoxType::String(l), LoxType::String(r)) => LoxType::Boolean(l > r),
+            _ => {
+                return incompatible_operands;
+            }
+        },
+        BinaryOperator::GreaterOrEqual => match (left, right) {
+            (LoxType::Number(l), LoxType::Number(r)) => LoxType::Boolean(l >= r),
+            (LoxType::String(l), LoxType::String(r)) => LoxType::Boolean(l >= r),
+            _ => {
+                return incompatible_operands;
+            }
+        },
+    };
+    Ok(r)
 }
 
 impl Eval for VariableExpression {
     fn eval(&self, ctx: Context) -> Result<LoxType> {
-        match ctx.get_at(self.maybe_distance, &self.name) {
+        match ctx.get_at(self.maybe_slot, &self.name) {
             Ok(value) => Ok(value.clone()),
             Err(_) => Err(Error::RuntimeError(ErrorDetail::new(
                 self.line,
@@ -127,12 +255,16 @@ impl Eval for VariableExpression {
 impl Eval for AssignExpression {
     fn eval(&self, ctx: Context) -> Result<LoxType> {
         let value = self.value.eval(ctx.clone())?;
-        match ctx.assign_at(self.maybe_distance, &self.name, value.clone()) {
+        match ctx.assign_at(self.maybe_slot, &self.name, value.clone()) {
             Ok(()) => Ok(value),
-            Err(_) => Err(Error::RuntimeError(ErrorDetail::new(
+            Err(AssignError::Undefined) => Err(Error::RuntimeError(ErrorDetail::new(
                 self.line,
                 format!("Undefined variable '{}'.", self.name),
             ))),
+            Err(AssignError::Constant) => Err(Error::RuntimeError(ErrorDetail::new(
+                self.line,
+                format!("Cannot assign to constant '{}'.", self.name),
+            ))),
         }
     }
 }
@@ -155,38 +287,150 @@ impl Eval for LogicalExpression {
                     self.right.eval(ctx)
                 }
             }
+            LogicalOperator::Coalesce => match left {
+                LoxType::Nil => self.right.eval(ctx),
+                left => Ok(left),
+            },
         }
     }
 }
 
-impl Eval for CallExpression {
+impl Eval for TernaryExpression {
     fn eval(&self, ctx: Context) -> Result<LoxType> {
+        if self.condition.eval(ctx.clone())?.is_truthy() {
+            self.then_branch.eval(ctx)
+        } else {
+            self.else_branch.eval(ctx)
+        }
+    }
+}
+
+impl CallExpression {
+    /// Evaluates what is called and the arguments, without making the call.
+    pub fn eval_callee_and_arguments(&self, ctx: Context) -> Result<(LoxType, Vec<LoxType>)> {
         let callee = self.callee.eval(ctx.clone())?;
         let arguments = self
             .arguments
             .iter()
             .map(|a| a.eval(ctx.clone()))
             .collect::<Result<Vec<LoxType>>>()?;
-        if let LoxType::Callable(callable) = callee {
-            if callable.arity() != arguments.len() {
-                return Err(Error::RuntimeError(ErrorDetail::new(
-                    self.line,
-                    format!(
-                        "Expected {} arguments but got {}.",
-                        callable.arity(),
-                        arguments.len()
-                    ),
-                )));
-            }
-            callable.call(arguments)
-        } else if let LoxType::Class(class) = callee {
-            class.instantiate(arguments, self.line)
-        } else {
-            Err(Error::RuntimeError(ErrorDetail::new(
-                self.line,
-                "Can only call functions and classes.",
-            )))
+        if self.keyword_arguments.is_empty() {
+            return Ok((callee, arguments));
         }
+        let keyword_arguments = self
+            .keyword_arguments
+            .iter()
+            .map(|(name, a)| Ok((name, a.eval(ctx.clone())?)))
+            .collect::<Result<Vec<_>>>()?;
+        let arguments = bind_keyword_arguments(&callee, arguments, keyword_arguments, self.line)?;
+        Ok((callee, arguments))
+    }
+}
+
+/// Puts the keyword arguments in the positions of the parameters with their names, after
+/// the positional ones.
+fn bind_keyword_arguments(
+    callee: &LoxType,
+    positional: Vec<LoxType>,
+    keyword_arguments: Vec<(&Symbol, LoxType)>,
+    line: u32,
+) -> Result<Vec<LoxType>> {
+    let error = |message: String| Err(Error::RuntimeError(ErrorDetail::new(line, message)));
+    let maybe_names = match callee {
+        LoxType::Callable(callable) => callable.parameter_names(),
+        LoxType::Class(class) => Some(class.init_parameter_names()),
+        _ => None,
+    };
+    let Some(names) = maybe_names else {
+        return error(format!("{callee} doesn't take keyword arguments."));
+    };
+
+    let mut slots: Vec<Option<LoxType>> = vec![None; names.len()];
+    let mut rest = vec![];
+    for (i, value) in positional.into_iter().enumerate() {
+        match slots.get_mut(i) {
+            Some(slot) => *slot = Some(value),
+            None => rest.push(value),
+        }
+    }
+    for (name, value) in keyword_arguments {
+        let Some(i) = names.iter().position(|n| n == name) else {
+            return error(format!("Unknown parameter '{name}'."));
+        };
+        if slots[i].is_some() {
+            return error(format!("Parameter '{name}' was given more than once."));
+        }
+        slots[i] = Some(value);
+    }
+
+    let mut arguments = vec![];
+    for (name, slot) in names.iter().zip(slots) {
+        match slot {
+            Some(value) => arguments.push(value),
+            None => return error(format!("Missing argument for parameter '{name}'.")),
+        }
+    }
+    arguments.extend(rest);
+    Ok(arguments)
+}
+
+impl Eval for CallExpression {
+    fn eval(&self, ctx: Context) -> Result<LoxType> {
+        let (callee, arguments) = self.eval_callee_and_arguments(ctx.clone())?;
+        call_value(ctx, callee, arguments, self.line)
+    }
+}
+
+pub(crate) fn check_arity(callable: &dyn LoxCallable, count: usize, line: u32) -> Result<()> {
+    let (min, max) = (callable.arity(), callable.max_arity());
+    if count < min || count > max {
+        let expected = if min == max {
+            min.to_string()
+        } else if max == usize::MAX {
+            format!("at least {min}")
+        } else {
+            format!("{min} to {max}")
+        };
+        return Err(Error::RuntimeError(ErrorDetail::new(
+            line,
+            format!("Expected {} arguments but got {}.", expected, count),
+        )));
+    }
+    Ok(())
+}
+
+/// Calls a function or instantiates a class called on `line`.
+pub(crate) fn call_value(
+    ctx: Context,
+    callee: LoxType,
+    arguments: Vec<LoxType>,
+    line: u32,
+) -> Result<LoxType> {
+    if let LoxType::Callable(callable) = &callee {
+        check_arity(callable.as_ref(), arguments.len(), line)?;
+        ctx.push_frame(&callee, line)?;
+        let result = callable
+            .call_with_ctx(ctx.clone(), arguments)
+            .map_err(|e| e.with_call_line(line));
+        ctx.pop_frame();
+        result
+    } else if let LoxType::Class(class) = &callee {
+        ctx.push_frame(&callee, line)?;
+        let result = class.clone().instantiate(arguments, line);
+        ctx.pop_frame();
+        result
+    } else {
+        Err(Error::RuntimeError(ErrorDetail::new(
+            line,
+            "Can only call functions and classes.",
+        )))
+    }
+}
+
+impl Eval for FunctionExpression {
+    fn eval(&self, ctx: Context) -> Result<LoxType> {
+        let function = LoxFunction::from_expression(self, ctx);
+        Ok(LoxType::Callable(Rc::new(function)))
     }
 }
 
@@ -195,6 +439,19 @@ impl Eval for GetExpression {
         let object = self.object.eval(ctx)?;
         if let LoxType::Instance(instance) = object {
             LoxInstance::get(instance, &self.name, self.line)
+        } else if let LoxType::List(list) = &object {
+            ListMethod::get(list, &self.name).ok_or_else(|| {
+                Error::RuntimeError(ErrorDetail::new(
+                    self.line,
+                    format!("Undefined property '{}'.", self.name),
+                ))
+            })
+        } else if self.is_optional && object == LoxType::Nil {
+            Ok(LoxType::Nil)
+        } else if let LoxType::Class(class) = object {
+            class
+                .get_static_method(&self.name, self.line)?
+                .into_property()
         } else {
             Err(Error::RuntimeError(ErrorDetail::new(
                 self.line,
@@ -208,7 +465,11 @@ impl Eval for SetExpression {
     fn eval(&self, ctx: Context) -> Result<LoxType> {
         let object = self.object.eval(ctx.clone())?;
         if let LoxType::Instance(instance) = object {
-            let value = self.value.eval(ctx)?;
+            let mut value = self.value.eval(ctx)?;
+            if let Some(operator) = self.maybe_operator {
+                let current = LoxInstance::get(instance.clone(), &self.name, self.line)?;
+                value = binary_operation(operator, current, value, self.line)?;
+            }
             Ok(LoxInstance::set(instance, &self.name, value))
         } else {
             Err(Error::RuntimeError(ErrorDetail::new(
@@ -219,23 +480,203 @@ impl Eval for SetExpression {
     }
 }
 
+impl Eval for ListExpression {
+    fn eval(&self, ctx: Context) -> Result<LoxType> {
+        let elements = self
+            .elements
+            .iter()
+            .map(|e| e.eval(ctx.clone()))
+            .collect::<Result<Vec<LoxType>>>()?;
+        Ok(LoxType::list(elements))
+    }
+}
+
+/// Checks an index into a list or string, `kind` names which one in the errors.
+fn sequence_index(index: LoxType, len: usize, kind: &str, line: u32) -> Result<usize> {
+    match index {
+        LoxType::Number(n) if n.fract() == 0.0 => {
+            if n >= 0.0 && (n as usize) < len {
+                Ok(n as usize)
+            } else {
+                Err(Error::RuntimeError(ErrorDetail::new(
+                    line,
+                    format!("{kind} index out of range."),
+                )))
+            }
+        }
+        _ => Err(Error::RuntimeError(ErrorDetail::new(
+            line,
+            format!("{kind} index must be an integer."),
+        ))),
+    }
+}
+
+impl Eval for MapExpression {
+    fn eval(&self, ctx: Context) -> Result<LoxType> {
+        let mut map = LoxMap::default();
+        for (key, value) in &self.entries {
+            let key = MapKey::from_value(key.eval(ctx.clone())?, self.line)?;
+            map.insert(key, value.eval(ctx.clone())?);
+        }
+        Ok(LoxType::Map(Rc::new(RefCell::new(map))))
+    }
+}
+
+impl Eval for IndexExpression {
+    fn eval(&self, ctx: Context) -> Result<LoxType> {
+        let object = self.object.eval(ctx.clone())?;
+        let index = self.index.eval(ctx)?;
+        match object {
+            LoxType::List(list) => {
+                let list = list.borrow();
+                let i = sequence_index(index, list.len(), "List", self.line)?;
+                Ok(list[i].clone())
+            }
+            // strings are indexed by character (Unicode scalar value) like `len` counts them,
+            // not by byte
+            LoxType::String(s) => {
+                let i = sequence_index(index, s.chars().count(), "String", self.line)?;
+                Ok(LoxType::String(s.chars().nth(i).unwrap().to_string()))
+            }
+            LoxType::Map(map) => {
+                let key = MapKey::from_value(index, self.line)?;
+                Ok(map.borrow().get(&key).cloned().unwrap_or(LoxType::Nil))
+            }
+            _ => Err(Error::RuntimeError(ErrorDetail::new(
+                self.line,
+                "Only lists, maps and strings can be indexed.",
+            ))),
+        }
+    }
+}
+
+impl Eval for IndexSetExpression {
+    fn eval(&self, ctx: Context) -> Result<LoxType> {
+        let object = self.object.eval(ctx.clone())?;
+        let index = self.index.eval(ctx.clone())?;
+        match object {
+            LoxType::List(list) => {
+                let mut value = self.value.eval(ctx)?;
+                let i = sequence_index(index, list.borrow().len(), "List", self.line)?;
+                if let Some(operator) = self.maybe_operator {
+                    let current = list.borrow()[i].clone();
+                    value = binary_operation(operator, current, value, self.line)?;
+                }
+                list.borrow_mut()[i] = value.clone();
+                Ok(value)
+            }
+            LoxType::Map(map) => {
+                let key = MapKey::from_value(index, self.line)?;
+                let mut value = self.value.eval(ctx)?;
+                if let Some(operator) = self.maybe_operator {
+                    let current = map.borrow().get(&key).cloned().unwrap_or(LoxType::Nil);
+                    value = binary_operation(operator, current, value, self.line)?;
+                }
+                map.borrow_mut().insert(key, value.clone());
+                Ok(value)
+            }
+            LoxType::String(_) => Err(Error::RuntimeError(ErrorDetail::new(
+                self.line,
+                "Strings can't be changed.",
+            ))),
+            _ => Err(Error::RuntimeError(ErrorDetail::new(
+                self.line,
+                "Only lists and maps can be indexed.",
+            ))),
+        }
+    }
+}
+
 impl Eval for ThisExpression {
     fn eval(&self, ctx: Context) -> Result<LoxType> {
-        Ok(ctx.get_at(self.maybe_distance, "this").unwrap())
+        Ok(ctx.get_local(self.maybe_slot.unwrap()))
     }
 }
 
 impl Eval for SuperExpression {
     fn eval(&self, ctx: Context) -> Result<LoxType> {
-        let superclass = ctx.get_at(self.maybe_distance, "super").unwrap();
-        let this: LoxType = ctx
-            .get_at(Some(self.maybe_distance.unwrap() - 1), "this")
-            .unwrap();
+        let slot = self.maybe_slot.unwrap();
+        let superclass = ctx.get_local(slot);
+        let this = ctx.get_local(Slot {
+            distance: slot.distance - 1,
+            index: 0,
+        });
 
         if let LoxType::Class(sc) = superclass {
-            sc.get_method(&self.method, this, self.line).map(|m| LoxType::Callable(Rc::new(m)))
+            sc.get_method(&self.method, this, self.line)?
+                .into_property()
         } else {
             panic!("Superclass is not a class.");
         }
     }
 }
+
+impl Eval for LetExpression {
+    fn eval(&self, ctx: Context) -> Result<LoxType> {
+        let value = self.value.eval(ctx.clone())?;
+        let let_ctx = ctx.new_child_ctx();
+        let_ctx.define(&self.name, value);
+        self.body.eval(let_ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{loxtype::LoxClass, symbol::Symbol};
+
+    use super::*;
+
+    #[test]
+    fn test_get_expression() {
+        let ctx = Context::new(Box::new(std::io::sink()));
+        let class_statement = ClassStatement {
+            name: Symbol::intern("Point"),
+            methods: Rc::new(vec![]),
+            static_methods: Rc::new(vec![]),
+            maybe_superclass: None,
+            line: 1,
+        };
+        let class = Rc::new(LoxClass::new(&class_statement, None, ctx.clone()));
+        let instance = class.instantiate(vec![], 1).unwrap();
+        if let LoxType::Instance(i) = &instance {
+            LoxInstance::set(i.clone(), &Symbol::intern("x"), LoxType::Number(1.0));
+        }
+
+        let expression: Box<dyn Expression> = Box::new(GetExpression {
+            object: Box::new(LiteralExpression(instance)),
+            name: Symbol::intern("x"),
+            is_optional: false,
+            line: 1,
+        });
+        assert_eq!(expression.eval(ctx).unwrap(), LoxType::Number(1.0));
+    }
+
+    #[test]
+    fn test_slot_out_of_range() {
+        // a slot the resolver got wrong, pointing past the globals
+        let ctx = Context::new(Box::new(std::io::sink())).new_child_ctx();
+        let slot = Slot {
+            distance: 5,
+            index: 0,
+        };
+        let variable = VariableExpression {
+            name: Symbol::intern("lost"),
+            maybe_slot: Some(slot),
+            line: 7,
+        };
+        let assign = AssignExpression {
+            name: Symbol::intern("lost"),
+            value: Box::new(NilExpression()),
+            maybe_slot: Some(slot),
+            line: 8,
+        };
+        assert_eq!(
+            variable.eval(ctx.clone()).unwrap_err().to_string(),
+            "Runtime error: [ line 7 ] : Undefined variable 'lost'.\n"
+        );
+        assert_eq!(
+            assign.eval(ctx).unwrap_err().to_string(),
+            "Runtime error: [ line 8 ] : Undefined variable 'lost'.\n"
+        );
+    }
+}
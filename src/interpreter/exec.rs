@@ -1,8 +1,11 @@
 use std::rc::Rc;
 
+use itertools::Itertools;
+
 use crate::{
     ast::{
-        BlockStatement, ClassStatement, ExpressionStatement, FunctionStatement, IfStatement,
+        BlockStatement, BreakStatement, CallExpression, ClassStatement, ContinueStatement,
+        DoWhileStatement, ExpressionStatement, ForEachStatement, FunctionStatement, IfStatement,
         PrintStatement, ReturnStatement, VarStatement, WhileStatement,
     },
     error::{Error, ErrorDetail},
@@ -11,11 +14,15 @@ use crate::{
     Result,
 };
 
-use super::{run_block, Context, Exec, StatementResult};
+use super::{run_block, Context, Exec, StatementResult, TailCall};
 
 impl Exec for PrintStatement {
     fn exec(&self, ctx: Context) -> Result<StatementResult> {
-        let mut out = self.expression.eval(ctx.clone())?.to_string();
+        let mut values = Vec::with_capacity(self.expressions.len());
+        for expression in &self.expressions {
+            values.push(expression.eval(ctx.clone())?);
+        }
+        let mut out = values.iter().join(" ");
         out.push('\n');
         match ctx.write_stdout(&out) {
             Ok(_) => Ok(StatementResult::Void),
@@ -29,8 +36,8 @@ impl Exec for PrintStatement {
 
 impl Exec for ExpressionStatement {
     fn exec(&self, ctx: Context) -> Result<StatementResult> {
-        let _ = self.0.eval(ctx)?;
-        Ok(StatementResult::Void)
+        let value = self.0.eval(ctx)?;
+        Ok(StatementResult::Value(value))
     }
 }
 
@@ -40,7 +47,11 @@ impl Exec for VarStatement {
             Some(exp) => exp.eval(ctx.clone())?,
             None => LoxType::Nil,
         };
-        ctx.define(&self.name, value);
+        if self.is_const {
+            ctx.define_const(&self.name, value);
+        } else {
+            ctx.define(&self.name, value);
+        }
         Ok(StatementResult::Void)
     }
 }
@@ -68,17 +79,86 @@ impl Exec for IfStatement {
 impl Exec for WhileStatement {
     fn exec(&self, ctx: Context) -> Result<StatementResult> {
         while self.condition.eval(ctx.clone())?.is_truthy() {
-            if let StatementResult::Return(r) = self.body.exec(ctx.clone())? {
-                return Ok(StatementResult::Return(r));
+            ctx.step(self.line)?;
+            match self.body.exec(ctx.clone())? {
+                StatementResult::Void | StatementResult::Value(_) | StatementResult::Continue => (),
+                StatementResult::Break => break,
+                result @ (StatementResult::Return(_) | StatementResult::TailCall(_)) => {
+                    return Ok(result)
+                }
+            }
+            if let Some(increment) = &self.maybe_increment {
+                increment.eval(ctx.clone())?;
+            }
+        }
+        Ok(StatementResult::Void)
+    }
+}
+
+impl Exec for ForEachStatement {
+    fn exec(&self, ctx: Context) -> Result<StatementResult> {
+        // the elements are copied first, so the body may change the collection
+        let elements: Vec<LoxType> = match self.iterable.eval(ctx.clone())? {
+            LoxType::List(list) => list.borrow().clone(),
+            LoxType::Map(map) => map.borrow().keys().map(|k| k.to_value()).collect(),
+            LoxType::String(s) => s.chars().map(|c| LoxType::String(c.to_string())).collect(),
+            _ => {
+                return Err(Error::RuntimeError(ErrorDetail::new(
+                    self.line,
+                    "Can only iterate over lists, maps and strings.",
+                )))
+            }
+        };
+        for element in elements {
+            ctx.step(self.line)?;
+            let element_ctx = ctx.new_child_ctx();
+            element_ctx.define(&self.name, element);
+            match self.body.exec(element_ctx)? {
+                StatementResult::Void | StatementResult::Value(_) | StatementResult::Continue => (),
+                StatementResult::Break => break,
+                result @ (StatementResult::Return(_) | StatementResult::TailCall(_)) => {
+                    return Ok(result)
+                }
             }
         }
         Ok(StatementResult::Void)
     }
 }
 
+impl Exec for DoWhileStatement {
+    fn exec(&self, ctx: Context) -> Result<StatementResult> {
+        loop {
+            ctx.step(self.line)?;
+            match self.body.exec(ctx.clone())? {
+                StatementResult::Void | StatementResult::Value(_) | StatementResult::Continue => (),
+                StatementResult::Break => break,
+                result @ (StatementResult::Return(_) | StatementResult::TailCall(_)) => {
+                    return Ok(result)
+                }
+            }
+            if !self.condition.eval(ctx.clone())?.is_truthy() {
+                break;
+            }
+        }
+        Ok(StatementResult::Void)
+    }
+}
+
+impl Exec for BreakStatement {
+    fn exec(&self, _ctx: Context) -> Result<StatementResult> {
+        Ok(StatementResult::Break)
+    }
+}
+
+impl Exec for ContinueStatement {
+    fn exec(&self, _ctx: Context) -> Result<StatementResult> {
+        Ok(StatementResult::Continue)
+    }
+}
+
 impl Exec for FunctionStatement {
     fn exec(&self, ctx: Context) -> Result<StatementResult> {
-        let function = LoxFunction::from_statement(self, ctx.clone(), None);
+        let function = LoxFunction::from_statement(self, ctx.clone());
         let callable = LoxType::Callable(Rc::new(function));
         ctx.define(&self.name, callable);
         Ok(StatementResult::Void)
@@ -88,6 +168,18 @@ impl Exec for FunctionStatement {
 impl Exec for ReturnStatement {
     fn exec(&self, ctx: Context) -> Result<StatementResult> {
         let r = match &self.maybe_expression {
+            Some(expression) if self.is_tail_call => {
+                let call = expression
+                    .as_any()
+                    .downcast_ref::<CallExpression>()
+                    .unwrap();
+                let (callee, arguments) = call.eval_callee_and_arguments(ctx)?;
+                return Ok(StatementResult::TailCall(TailCall {
+                    callee,
+                    arguments,
+                    line: call.line,
+                }));
+            }
             Some(expression) => expression.eval(ctx)?,
             None => LoxType::Nil,
         };
@@ -112,10 +204,9 @@ impl Exec for ClassStatement {
             })
             .transpose()?;
 
-        ctx.define(&self.name, LoxType::Nil);
+        // methods close over `ctx` and find the class there once it is defined
         let class = LoxClass::new(self, maybe_superclass, ctx.clone());
-        ctx.assign_at(Some(0), &self.name, LoxType::Class(Rc::new(class)))
-            .unwrap();
+        ctx.define(&self.name, LoxType::Class(Rc::new(class)));
         Ok(StatementResult::Void)
     }
 }
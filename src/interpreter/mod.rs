@@ -1,105 +1,356 @@
+mod cycles;
 mod env;
 mod eval;
 mod exec;
 
-use std::cell::RefCell;
-use std::io::{stdout, Stdout};
+use std::cell::{Cell, RefCell};
+#[cfg(test)]
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Display};
+use std::io;
+#[cfg(not(test))]
+use std::io::{stdin, Stdin};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use self::cycles::{collect, Instances};
 
-use crate::ast::Statement;
-use crate::loxtype::LoxType;
-use crate::native_fns::Clock;
+use crate::ast::{Slot, Statement};
+use crate::error::{Error, ErrorDetail};
+use crate::loxtype::{LoxCallable, LoxClass, LoxInstance, LoxType};
+use crate::native_fns::{
+    Abs, Acos, Asin, Assert, AssertEq, Atan, Atan2, Ceil, Clamp, Clock, Cos, Exit, Floor, Format,
+    GetField, IndexOf, Input, IsInstance, Keys, Len, Lower, Max, Min, Num, Pi, Pow, Random,
+    RandomInt, ReadFile, Rng, Round, Seed, SetField, Sin, Split, Sqrt, Str, Substr, Tan, Trim,
+    Type, Upper, Write, WriteFile, E,
+};
 use crate::parser::Parser;
-use crate::resolver::resolve;
+use crate::resolver::{resolve_with_warnings, ResolveOptions};
 use crate::scanner::scan_tokens;
+use crate::symbol::Symbol;
 use crate::Result;
 
-pub use self::env::{Environment, UndefinedVariable};
+pub use self::cycles::Marker;
+pub use self::env::{AssignError, Environment, UndefinedVariable};
+pub(crate) use self::eval::{call_value, check_arity};
 
 pub enum StatementResult {
     Void,
+    /// Value of an expression statement, only looked at for the last statement of a program.
+    Value(LoxType),
+    Break,
+    Continue,
     Return(LoxType),
+    /// `return f(x);` in a function. The function making the call returns first, so tail
+    /// recursion runs in a loop instead of growing the stack.
+    TailCall(TailCall),
+}
+
+pub struct TailCall {
+    pub callee: LoxType,
+    pub arguments: Vec<LoxType>,
+    pub line: u32,
+}
+
+/// Destination of everything the program prints.
+struct Output(Box<dyn io::Write>);
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Output")
+    }
 }
 
+/// Writer that keeps the output in memory so tests can look at it.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Calls deeper than this are reported as a stack overflow instead of exhausting the Rust
+/// stack, provided the thread running the interpreter has room for that many calls. The
+/// `rlox` binary sizes its thread for the limit; an embedding host has to do the same.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// A call that hasn't returned yet.
+#[derive(Debug)]
+struct Frame {
+    callee: String,
+    line: u32,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "in {} called at line {}", self.callee, self.line)
+    }
+}
+
+/// Counts loop iterations and calls, the steps a program can't take infinitely many of
+/// without running forever.
+#[derive(Debug, Default)]
+struct Steps {
+    taken: Cell<u64>,
+    maybe_limit: Cell<Option<u64>>,
+    /// Set from another thread, like a Ctrl-C handler, to stop the program at its next step.
+    interrupted: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+struct CallStack {
+    frames: Vec<Frame>,
+    max_depth: usize,
+}
+
+/// Where `input()` reads from: the process's stdin, or lines queued by the test.
+#[cfg(not(test))]
+type InputSource = Stdin;
+#[cfg(test)]
+type InputSource = VecDeque<String>;
+
 #[derive(Debug, Clone)]
 pub struct Context {
     globals: Rc<RefCell<Environment>>,
     env: Rc<RefCell<Environment>>,
-    stout: Rc<RefCell<Stdout>>,
+    stout: Rc<RefCell<Output>>,
+    stdin: Rc<RefCell<InputSource>>,
+    /// Files `readFile` and `writeFile` use in tests, instead of the real file system.
     #[cfg(test)]
-    test_stout: Rc<RefCell<String>>,
+    files: Rc<RefCell<HashMap<String, String>>>,
+    call_stack: Rc<RefCell<CallStack>>,
+    instances: Rc<RefCell<Instances>>,
+    steps: Rc<Steps>,
 }
 
 impl Context {
-    pub fn new() -> Self {
+    pub fn new(out: Box<dyn io::Write>) -> Self {
         let globals = Environment::new(None);
         let env = globals.clone();
         Self {
             globals,
             env,
-            stout: Rc::new(RefCell::new(stdout())),
+            stout: Rc::new(RefCell::new(Output(out))),
+            #[cfg(not(test))]
+            stdin: Rc::new(RefCell::new(stdin())),
+            #[cfg(test)]
+            stdin: Rc::new(RefCell::new(VecDeque::new())),
             #[cfg(test)]
-            test_stout: Rc::new(RefCell::new(String::new())),
+            files: Rc::new(RefCell::new(HashMap::new())),
+            call_stack: Rc::new(RefCell::new(CallStack {
+                frames: vec![],
+                max_depth: DEFAULT_MAX_CALL_DEPTH,
+            })),
+            instances: Rc::new(RefCell::new(Instances::default())),
+            steps: Rc::new(Steps::default()),
         }
     }
 
-    pub fn define(&self, name: &str, value: LoxType) {
+    pub fn define(&self, name: &Symbol, value: LoxType) {
         self.env.borrow_mut().define(name, value);
     }
 
+    pub fn define_const(&self, name: &Symbol, value: LoxType) {
+        self.env.borrow_mut().define_const(name, value);
+    }
+
+    /// Variables the resolver didn't find in a local scope are globals and looked up by name.
     pub fn assign_at(
         &self,
-        maybe_distance: Option<u32>,
-        name: &str,
+        maybe_slot: Option<Slot>,
+        name: &Symbol,
         value: LoxType,
-    ) -> std::result::Result<(), UndefinedVariable> {
-        if let Some(distance) = maybe_distance {
-            self.env.borrow_mut().assign_at(distance, name, value)
+    ) -> std::result::Result<(), AssignError> {
+        if let Some(slot) = maybe_slot {
+            Ok(self
+                .env
+                .borrow_mut()
+                .assign_at(slot.distance, slot.index, value)?)
         } else {
-            self.globals.borrow_mut().assign_at(0, name, value)
+            self.globals.borrow_mut().assign_global(name, value)
         }
     }
 
     pub fn get_at(
         &self,
-        maybe_distance: Option<u32>,
-        name: &str,
+        maybe_slot: Option<Slot>,
+        name: &Symbol,
     ) -> std::result::Result<LoxType, UndefinedVariable> {
-        if let Some(distance) = maybe_distance {
-            self.env.borrow().get_at(distance, name)
+        if let Some(slot) = maybe_slot {
+            self.env.borrow().get_at(slot.distance, slot.index)
         } else {
-            self.globals.borrow().get_at(0, name)
+            self.globals.borrow().get_global(name)
         }
     }
 
-    #[cfg(not(test))]
-    pub fn write_stdout(&self, t: &str) -> std::result::Result<(), std::io::Error> {
-        use std::io::Write;
+    /// Reads a local the resolver always finds in a scope, like `this` and `super`.
+    pub fn get_local(&self, slot: Slot) -> LoxType {
+        self.env
+            .borrow()
+            .get_at(slot.distance, slot.index)
+            .expect("local was defined before it is read")
+    }
 
-        let mut out = self.stout.borrow_mut();
+    pub fn write_stdout(&self, t: &str) -> std::result::Result<(), std::io::Error> {
+        let out = &mut self.stout.borrow_mut().0;
         out.write_all(t.as_bytes()).and_then(|_| out.flush())
     }
 
+    /// Reads a line without its line ending, `None` at the end of the input.
+    #[cfg(not(test))]
+    pub fn read_line(&self) -> Option<String> {
+        let mut line = String::new();
+        match self.stdin.borrow().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                let len = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(len);
+                Some(line)
+            }
+        }
+    }
+
     #[cfg(test)]
-    pub fn write_stdout(&self, t: &str) -> std::result::Result<(), std::io::Error> {
-        self.test_stout.borrow_mut().push_str(t);
+    pub fn read_line(&self) -> Option<String> {
+        self.stdin.borrow_mut().pop_front()
+    }
+
+    /// Contents of the file at `path`, `None` if it can't be read.
+    #[cfg(not(test))]
+    pub fn read_file(&self, path: &str) -> Option<String> {
+        std::fs::read_to_string(path).ok()
+    }
+
+    #[cfg(test)]
+    pub fn read_file(&self, path: &str) -> Option<String> {
+        self.files.borrow().get(path).cloned()
+    }
+
+    /// Replaces the contents of the file at `path`, returning whether that succeeded.
+    #[cfg(not(test))]
+    pub fn write_file(&self, path: &str, contents: &str) -> bool {
+        std::fs::write(path, contents).is_ok()
+    }
+
+    /// Tests have no directories, so only paths without one can be written.
+    #[cfg(test)]
+    pub fn write_file(&self, path: &str, contents: &str) -> bool {
+        if path.contains('/') {
+            return false;
+        }
+        self.files
+            .borrow_mut()
+            .insert(path.to_owned(), contents.to_owned());
+        true
+    }
+
+    /// Takes a step and fails once the limit set by `Interpreter::run_with_limit` is used up
+    /// or the program was interrupted.
+    pub fn step(&self, line: u32) -> Result<()> {
+        let taken = self.steps.taken.get();
+        if self
+            .steps
+            .maybe_limit
+            .get()
+            .is_some_and(|limit| taken >= limit)
+        {
+            return Err(Error::RuntimeError(ErrorDetail::new(
+                line,
+                "Execution limit exceeded.",
+            )));
+        }
+        if self.steps.interrupted.swap(false, Ordering::Relaxed) {
+            return Err(Error::RuntimeError(ErrorDetail::new(line, "Interrupted.")));
+        }
+        self.steps.taken.set(taken + 1);
+        Ok(())
+    }
+
+    pub fn push_frame(&self, callee: &LoxType, line: u32) -> Result<()> {
+        self.step(line)?;
+        let mut call_stack = self.call_stack.borrow_mut();
+        if call_stack.frames.len() >= call_stack.max_depth {
+            return Err(Error::RuntimeError(ErrorDetail::new(
+                line,
+                "Stack overflow.",
+            )));
+        }
+        call_stack.frames.push(Frame {
+            callee: callee.to_string(),
+            line,
+        });
         Ok(())
     }
 
+    pub fn pop_frame(&self) {
+        self.call_stack.borrow_mut().frames.pop();
+    }
+
+    /// The active calls, innermost first. Deep recursion is cut short after a few frames.
+    pub fn stack_trace(&self) -> Vec<String> {
+        const MAX_FRAMES: usize = 10;
+        let frames = &self.call_stack.borrow().frames;
+        let mut trace = frames
+            .iter()
+            .rev()
+            .take(MAX_FRAMES)
+            .map(Frame::to_string)
+            .collect::<Vec<_>>();
+        if frames.len() > MAX_FRAMES {
+            trace.push(format!("... {} more", frames.len() - MAX_FRAMES));
+        }
+        trace
+    }
+
+    pub fn track_instance(&self, instance: &Rc<RefCell<LoxInstance>>) {
+        let is_due = self.instances.borrow_mut().track(instance);
+        if is_due {
+            collect(self);
+        }
+    }
+
+    /// A context without any variables that shares the input, output and limits of this one.
+    fn fresh(&self) -> Self {
+        let globals = Environment::new(None);
+        Context {
+            globals: globals.clone(),
+            env: globals,
+            stout: self.stout.clone(),
+            stdin: self.stdin.clone(),
+            #[cfg(test)]
+            files: self.files.clone(),
+            call_stack: Rc::new(RefCell::new(CallStack {
+                frames: vec![],
+                max_depth: self.call_stack.borrow().max_depth,
+            })),
+            instances: Rc::new(RefCell::new(Instances::default())),
+            steps: self.steps.clone(),
+        }
+    }
+
     pub fn new_child_ctx(&self) -> Self {
         Context {
             globals: self.globals.clone(),
             env: Environment::new(Some(self.env.clone())),
             stout: self.stout.clone(),
+            stdin: self.stdin.clone(),
             #[cfg(test)]
-            test_stout: self.test_stout.clone(),
+            files: self.files.clone(),
+            call_stack: self.call_stack.clone(),
+            instances: self.instances.clone(),
+            steps: self.steps.clone(),
         }
     }
-
-    #[cfg(test)]
-    pub fn into_writer(self) -> String {
-        self.test_stout.borrow().clone()
-    }
 }
 
 pub trait Eval {
@@ -113,7 +364,7 @@ pub trait Exec {
 pub(crate) fn run_block(
     ctx: Context,
     statements: &[Box<dyn Statement>],
-    maybe_params_args: Option<(&[String], Vec<LoxType>)>,
+    maybe_params_args: Option<(&[Symbol], Vec<LoxType>)>,
 ) -> crate::Result<StatementResult> {
     let block_ctx = ctx.new_child_ctx();
     if let Some((params, args)) = maybe_params_args {
@@ -123,37 +374,352 @@ pub(crate) fn run_block(
         }
     }
     for statement in statements.iter() {
-        if let StatementResult::Return(r) = statement.exec(block_ctx.clone())? {
-            return Ok(StatementResult::Return(r));
+        match statement.exec(block_ctx.clone())? {
+            StatementResult::Void | StatementResult::Value(_) => (),
+            result => return Ok(result),
         }
     }
     Ok(StatementResult::Void)
 }
+
 pub struct Interpreter {
     ctx: Context,
+    keep_going: bool,
+    warn_shadowing: bool,
+    /// Reported by the resolver and not taken by the host yet.
+    warnings: RefCell<Vec<ErrorDetail>>,
+    chained_comparisons: bool,
+    file_access: bool,
+    #[cfg(test)]
+    test_stout: SharedBuffer,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
+    #[cfg(not(test))]
     pub fn new() -> Self {
-        let ctx = Context::new();
-        ctx.define("clock", LoxType::Callable(Rc::new(Clock())));
-        Self { ctx }
+        Self::new_with_writer(Box::new(io::stdout()))
+    }
+
+    #[cfg(test)]
+    pub fn new() -> Self {
+        let test_stout = SharedBuffer::default();
+        Self {
+            test_stout: test_stout.clone(),
+            ..Self::new_with_writer(Box::new(test_stout))
+        }
+    }
+
+    /// An interpreter whose `input()` calls return `lines` one after another, and nil after
+    /// the last one.
+    #[cfg(test)]
+    pub fn new_with_input(lines: &[&str]) -> Self {
+        let interpreter = Self::new();
+        interpreter
+            .ctx
+            .stdin
+            .borrow_mut()
+            .extend(lines.iter().map(|line| line.to_string()));
+        interpreter
+    }
+
+    /// Creates an interpreter that sends the output of `print` and `write` to `out`.
+    pub fn new_with_writer(out: Box<dyn io::Write>) -> Self {
+        let interpreter = Self {
+            ctx: Context::new(out),
+            keep_going: false,
+            warn_shadowing: false,
+            warnings: RefCell::new(vec![]),
+            chained_comparisons: false,
+            file_access: false,
+            #[cfg(test)]
+            test_stout: SharedBuffer::default(),
+        };
+        interpreter.define_natives();
+        interpreter
+    }
+
+    fn define_natives(&self) {
+        let interpreter = self;
+        interpreter.define_native("clock", Rc::new(Clock()));
+        interpreter.define_native("keys", Rc::new(Keys()));
+        interpreter.define_native("write", Rc::new(Write()));
+        interpreter.define_native("input", Rc::new(Input()));
+        interpreter.define_native("sqrt", Rc::new(Sqrt()));
+        interpreter.define_native("abs", Rc::new(Abs()));
+        interpreter.define_native("floor", Rc::new(Floor()));
+        interpreter.define_native("ceil", Rc::new(Ceil()));
+        interpreter.define_native("round", Rc::new(Round()));
+        interpreter.define_native("pow", Rc::new(Pow()));
+        interpreter.define_native("min", Rc::new(Min()));
+        interpreter.define_native("max", Rc::new(Max()));
+        interpreter.define_native("clamp", Rc::new(Clamp()));
+        interpreter.define_native("pi", Rc::new(Pi()));
+        interpreter.define_native("e", Rc::new(E()));
+        interpreter.define_native("sin", Rc::new(Sin()));
+        interpreter.define_native("cos", Rc::new(Cos()));
+        interpreter.define_native("tan", Rc::new(Tan()));
+        interpreter.define_native("asin", Rc::new(Asin()));
+        interpreter.define_native("acos", Rc::new(Acos()));
+        interpreter.define_native("atan", Rc::new(Atan()));
+        interpreter.define_native("atan2", Rc::new(Atan2()));
+        interpreter.define_native("type", Rc::new(Type()));
+        interpreter.define_native("isInstance", Rc::new(IsInstance()));
+        interpreter.define_native("getField", Rc::new(GetField()));
+        interpreter.define_native("setField", Rc::new(SetField()));
+        interpreter.define_native("str", Rc::new(Str()));
+        interpreter.define_native("num", Rc::new(Num()));
+        interpreter.define_native("len", Rc::new(Len()));
+        interpreter.define_native("upper", Rc::new(Upper()));
+        interpreter.define_native("lower", Rc::new(Lower()));
+        interpreter.define_native("substr", Rc::new(Substr()));
+        interpreter.define_native("indexOf", Rc::new(IndexOf()));
+        interpreter.define_native("trim", Rc::new(Trim()));
+        interpreter.define_native("split", Rc::new(Split()));
+        interpreter.define_native("format", Rc::new(Format()));
+        interpreter.define_native("assert", Rc::new(Assert()));
+        interpreter.define_native("assertEq", Rc::new(AssertEq()));
+        interpreter.define_native("exit", Rc::new(Exit()));
+        if interpreter.file_access {
+            interpreter.define_file_natives();
+        }
+
+        let rng = Rng::new();
+        interpreter.define_native("random", Rc::new(Random(rng.clone())));
+        interpreter.define_native("randomInt", Rc::new(RandomInt(rng.clone())));
+        interpreter.define_native("seed", Rc::new(Seed(rng)));
+
+        interpreter.define_math();
+    }
+
+    fn define_file_natives(&self) {
+        self.define_native("readFile", Rc::new(ReadFile()));
+        self.define_native("writeFile", Rc::new(WriteFile()));
+    }
+
+    /// Defines `Math`, an instance holding the math natives and constants as fields, so they
+    /// can be used as `Math.sqrt(2)` and `Math.PI`.
+    fn define_math(&self) {
+        let class = Rc::new(LoxClass::native("Math", self.ctx.clone()));
+        let math = class
+            .instantiate(vec![], 0)
+            .expect("native classes have no init");
+        let LoxType::Instance(instance) = &math else {
+            unreachable!("classes are instantiated as instances")
+        };
+        let natives: [(&str, Rc<dyn LoxCallable>); 16] = [
+            ("sqrt", Rc::new(Sqrt())),
+            ("abs", Rc::new(Abs())),
+            ("floor", Rc::new(Floor())),
+            ("ceil", Rc::new(Ceil())),
+            ("round", Rc::new(Round())),
+            ("pow", Rc::new(Pow())),
+            ("min", Rc::new(Min())),
+            ("max", Rc::new(Max())),
+            ("clamp", Rc::new(Clamp())),
+            ("sin", Rc::new(Sin())),
+            ("cos", Rc::new(Cos())),
+            ("tan", Rc::new(Tan())),
+            ("asin", Rc::new(Asin())),
+            ("acos", Rc::new(Acos())),
+            ("atan", Rc::new(Atan())),
+            ("atan2", Rc::new(Atan2())),
+        ];
+        for (name, native) in natives {
+            LoxInstance::set(
+                instance.clone(),
+                &Symbol::intern(name),
+                LoxType::Callable(native),
+            );
+        }
+        LoxInstance::set(
+            instance.clone(),
+            &Symbol::intern("PI"),
+            std::f64::consts::PI.into(),
+        );
+        LoxInstance::set(
+            instance.clone(),
+            &Symbol::intern("E"),
+            std::f64::consts::E.into(),
+        );
+        self.ctx.define(&Symbol::intern("Math"), math);
+    }
+
+    /// Forgets everything the programs run so far defined, as if this was a new interpreter
+    /// with the same settings.
+    pub fn reset(&mut self) {
+        let fresh = self.ctx.fresh();
+        let old = std::mem::replace(&mut self.ctx, fresh);
+        self.define_natives();
+        // frees the old values even if they reference each other
+        old.globals.borrow_mut().clear();
+        collect(&old);
+    }
+
+    /// The global variables defined by programs, sorted by name. Natives and `Math` aren't
+    /// included.
+    pub fn globals(&self) -> Vec<(String, LoxType)> {
+        self.ctx
+            .globals
+            .borrow()
+            .globals()
+            .into_iter()
+            .filter(|(_, value)| match value {
+                LoxType::Callable(callable) => callable.as_function().is_some(),
+                LoxType::Instance(instance) => !instance.borrow().class().is_native,
+                _ => true,
+            })
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    /// Makes a native function available to Lox code as a global.
+    ///
+    /// The interpreter checks the number of arguments against `arity` and `max_arity` before
+    /// calling it. Errors returned from the native should be `RuntimeError`s with line 0, the
+    /// line of the call is filled in when they propagate.
+    pub fn define_native(&self, name: &str, native: Rc<dyn LoxCallable>) {
+        self.ctx
+            .define(&Symbol::intern(name), LoxType::Callable(native));
+    }
+
+    /// Defines or overwrites a global, so a host embedding the interpreter can pass values
+    /// to the scripts it runs.
+    pub fn set_global(&self, name: &str, value: LoxType) {
+        self.ctx
+            .globals
+            .borrow_mut()
+            .define(&Symbol::intern(name), value);
+    }
+
+    /// Reads a global after running a script, `None` if it isn't defined.
+    pub fn get_global(&self, name: &str) -> Option<LoxType> {
+        let name = Symbol::get(name)?;
+        self.ctx.globals.borrow().get_global(&name).ok()
+    }
+
+    /// When set, a runtime error only ends the top-level statement it happened in and the
+    /// program continues with the next one. The errors are reported together at the end.
+    pub fn set_keep_going(&mut self, keep_going: bool) {
+        self.keep_going = keep_going;
+    }
+
+    /// When set, locals that shadow a variable of an enclosing local scope are reported as
+    /// warnings, see `take_warnings`.
+    pub fn set_warn_shadowing(&mut self, warn_shadowing: bool) {
+        self.warn_shadowing = warn_shadowing;
+    }
+
+    /// Warnings for the programs run since the last call. They don't stop a program from
+    /// running, so it's up to the host to show them.
+    pub fn take_warnings(&self) -> Vec<ErrorDetail> {
+        self.warnings.take()
+    }
+
+    /// When set, comparisons can be chained like in `0 <= x < 10`, which is an error
+    /// otherwise.
+    pub fn set_chained_comparisons(&mut self, chained_comparisons: bool) {
+        self.chained_comparisons = chained_comparisons;
+    }
+
+    /// Defines `readFile` and `writeFile`, which give scripts access to every file the process
+    /// can read and write. They aren't there by default, so a host only hands that out on
+    /// purpose.
+    pub fn enable_file_access(&mut self) {
+        self.file_access = true;
+        self.define_file_natives();
     }
 
+    /// Limits how deeply calls can nest before a "Stack overflow." runtime error is raised.
+    pub fn set_max_call_depth(&self, max_depth: usize) {
+        self.ctx.call_stack.borrow_mut().max_depth = max_depth;
+    }
+
+    /// Runs a script, which may end early with a `return;` outside of any function.
     pub fn run(&self, source: &str) -> Result<()> {
+        self.execute(source, true).map(|_| ())
+    }
+
+    /// Runs untrusted code that may not terminate. Every loop iteration and call is a step,
+    /// after `max_steps` of them the program is stopped with a runtime error.
+    pub fn run_with_limit(&self, source: &str, max_steps: u64) -> Result<()> {
+        self.ctx.steps.taken.set(0);
+        self.ctx.steps.maybe_limit.set(Some(max_steps));
+        let result = self.run(source);
+        self.ctx.steps.maybe_limit.set(None);
+        result
+    }
+
+    /// Setting the returned flag, for example from a Ctrl-C handler, stops the running program
+    /// with an "Interrupted." runtime error. The flag is cleared when that happens.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        self.ctx.steps.interrupted.clone()
+    }
+
+    /// Runs the source and returns the value of its last statement if that is an expression
+    /// statement, nil otherwise. It's meant for lines typed at the prompt, so `return`
+    /// outside of a function is an error.
+    pub fn eval_str(&self, source: &str) -> Result<LoxType> {
+        self.execute(source, false)
+    }
+
+    fn execute(&self, source: &str, is_script: bool) -> Result<LoxType> {
         let tokens = scan_tokens(source)?;
-        let mut statements = Parser::new(&tokens).parse()?;
-        resolve(&mut statements)?;
+        let mut statements = Parser::new(&tokens)
+            .with_chained_comparisons(self.chained_comparisons)
+            .parse()?;
+        let options = ResolveOptions {
+            warn_shadowing: self.warn_shadowing,
+            is_script,
+        };
+        let warnings = resolve_with_warnings(&mut statements, options)?;
+        self.warnings.borrow_mut().extend(warnings);
 
+        let mut value = LoxType::Nil;
+        let mut runtime_errors = vec![];
         for statement in statements {
-            statement.exec(self.ctx.clone())?;
+            value = match statement.exec(self.ctx.clone()) {
+                // tests check the output written before the exit instead of ending the process
+                #[cfg(test)]
+                Err(Error::Exit(_)) => return Ok(LoxType::Nil),
+                Err(Error::RuntimeError(detail)) if self.keep_going => {
+                    runtime_errors.push(detail);
+                    LoxType::Nil
+                }
+                Err(e) => return Err(e),
+                Ok(StatementResult::Value(v)) => v,
+                // a top-level `return;` ends the script
+                Ok(StatementResult::Return(_)) => break,
+                Ok(_) => LoxType::Nil,
+            }
         }
-        Ok(())
+        if runtime_errors.is_empty() {
+            Ok(value)
+        } else {
+            Err(Error::RuntimeErrors(runtime_errors))
+        }
+    }
+
+    /// Like `eval_str`, but when running fails the globals are put back the way they were,
+    /// so a line typed at the prompt that fails halfway doesn't leave some of its definitions
+    /// behind. Only the variables are restored, changes to the values they hold remain.
+    pub fn eval_str_or_rollback(&self, source: &str) -> Result<LoxType> {
+        let snapshot = self.ctx.globals.borrow().snapshot_globals();
+        let result = self.eval_str(source);
+        if result.is_err() {
+            self.ctx.globals.borrow_mut().restore_globals(snapshot);
+        }
+        result
     }
 
     #[cfg(test)]
     pub fn get_output(self) -> String {
-        self.ctx.into_writer()
+        String::from_utf8(self.test_stout.0.borrow().clone()).unwrap()
     }
 }
 
@@ -165,11 +731,452 @@ mod tests {
 
     use super::*;
 
+    /// Native that prints its argument twice through the context of the call site.
+    #[derive(Debug)]
+    struct Echo();
+
+    impl Display for Echo {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "<native fn echo>")
+        }
+    }
+
+    impl LoxCallable for Echo {
+        fn arity(&self) -> usize {
+            1
+        }
+
+        fn call(&self, _arguments: Vec<LoxType>) -> Result<LoxType> {
+            unreachable!("echo needs a context")
+        }
+
+        fn call_with_ctx(&self, ctx: Context, arguments: Vec<LoxType>) -> Result<LoxType> {
+            let out = format!("{0} {0}\n", arguments[0]);
+            ctx.write_stdout(&out).unwrap();
+            Ok(LoxType::Nil)
+        }
+    }
+
+    #[test]
+    fn test_input() {
+        let interpreter = Interpreter::new_with_input(&["Alice"]);
+        interpreter
+            .run("var name = input(); print \"Hello, \" + name + \"!\"; print input();")
+            .unwrap();
+        assert_eq!(interpreter.get_output(), "Hello, Alice!\nnil\n");
+    }
+
+    #[test]
+    fn test_input_lines() {
+        let interpreter = Interpreter::new_with_input(&["3", "4"]);
+        interpreter
+            .run(
+                r#"
+                var width = num(input());
+                var height = num(input());
+                print width * height;
+                print input() ?? "done";
+                "#,
+            )
+            .unwrap();
+        assert_eq!(interpreter.get_output(), "12\ndone\n");
+    }
+
+    #[test]
+    fn test_host_globals() {
+        let interpreter = Interpreter::new();
+        interpreter.set_global("limit", LoxType::Number(3.0));
+        interpreter.set_global("greeting", LoxType::String("hi".to_owned()));
+        interpreter
+            .run(
+                r#"
+                fun countTo(n) {
+                    var total = 0;
+                    for (var i = 1; i <= n; i = i + 1) total = total + i;
+                    return total;
+                }
+                var result = countTo(limit);
+                greeting = greeting + "!";
+                print greeting;
+                "#,
+            )
+            .unwrap();
+        assert_eq!(interpreter.get_global("result"), Some(LoxType::Number(6.0)));
+        assert_eq!(
+            interpreter.get_global("greeting"),
+            Some(LoxType::String("hi!".to_owned()))
+        );
+        assert_eq!(interpreter.get_global("missing"), None);
+
+        // a later run sees the values set in between
+        interpreter.set_global("limit", LoxType::Number(4.0));
+        interpreter.run("result = countTo(limit);").unwrap();
+        assert_eq!(
+            interpreter.get_global("result"),
+            Some(LoxType::Number(10.0))
+        );
+        assert_eq!(interpreter.get_output(), "hi!\n");
+    }
+
+    #[test]
+    fn test_read_write_file() {
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter.run("readFile(\"notes.txt\");").is_err());
+        interpreter.enable_file_access();
+        interpreter
+            .run(
+                r#"
+                print writeFile("notes.txt", "line 1\nline 2");
+                print readFile("notes.txt");
+                print readFile("missing.txt");
+                "#,
+            )
+            .unwrap();
+        // natives are defined again after a reset
+        interpreter.reset();
+        interpreter.run("print readFile(\"notes.txt\");").unwrap();
+        assert_eq!(
+            interpreter.get_output(),
+            "true\nline 1\nline 2\nnil\nline 1\nline 2\n"
+        );
+    }
+
+    #[test]
+    fn test_eval_str() {
+        let interpreter = Interpreter::new();
+        assert_eq!(
+            interpreter.eval_str("1 + 2;").unwrap(),
+            LoxType::Number(3.0)
+        );
+        assert_eq!(
+            interpreter.eval_str("var a = \"x\"; a + a;").unwrap(),
+            LoxType::String("xx".to_owned())
+        );
+        // globals persist between calls
+        assert_eq!(
+            interpreter.eval_str("a;").unwrap(),
+            LoxType::String("x".to_owned())
+        );
+        assert_eq!(interpreter.eval_str("1; print 2;").unwrap(), LoxType::Nil);
+        assert_eq!(interpreter.eval_str("").unwrap(), LoxType::Nil);
+        assert_eq!(
+            interpreter.eval_str("fun f() { 5; } f();").unwrap(),
+            LoxType::Nil
+        );
+        assert_eq!(
+            interpreter.eval_str("return;").unwrap_err().to_string(),
+            "Resolver error(s):\n[ line 1 ] : Can't return from top-level code.\n"
+        );
+    }
+
+    #[test]
+    fn test_eval_str_or_rollback() {
+        let interpreter = Interpreter::new();
+        interpreter
+            .eval_str_or_rollback("var a = 1; var list = [];")
+            .unwrap();
+
+        // the definitions and assignments before the error are undone
+        let err = interpreter
+            .eval_str_or_rollback("var b = 2; a = 10; fun f() {} list.push(1); nope;")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Runtime error: [ line 1 ] : Undefined variable 'nope'.\n"
+        );
+        assert_eq!(interpreter.get_global("a"), Some(LoxType::Number(1.0)));
+        assert_eq!(interpreter.get_global("b"), None);
+        assert_eq!(interpreter.get_global("f"), None);
+        // but not changes to the values themselves
+        assert_eq!(interpreter.get_global("list").unwrap().to_string(), "[1]");
+
+        // without an error everything stays
+        interpreter.eval_str_or_rollback("var b = 2;").unwrap();
+        assert_eq!(interpreter.get_global("b"), Some(LoxType::Number(2.0)));
+
+        // `eval_str` keeps what ran before the error
+        assert!(interpreter.eval_str("var c = 3; nope;").is_err());
+        assert_eq!(interpreter.get_global("c"), Some(LoxType::Number(3.0)));
+    }
+
+    #[test]
+    fn test_new_with_writer() {
+        let out = SharedBuffer::default();
+        let interpreter = Interpreter::new_with_writer(Box::new(out.clone()));
+        interpreter.run("print \"hello\"; write(1 + 2);").unwrap();
+        assert_eq!(*out.0.borrow(), b"hello\n3");
+        // the default test output is not involved
+        assert_eq!(interpreter.get_output(), "");
+    }
+
+    #[test]
+    fn test_native_with_ctx() {
+        let interpreter = Interpreter::new();
+        interpreter.define_native("echo", Rc::new(Echo()));
+        interpreter
+            .run("fun f(x) { echo(x); } f(\"hi\"); print clock() > 0;")
+            .unwrap();
+        assert_eq!(interpreter.get_output(), "hi hi\ntrue\n");
+    }
+
+    #[test]
+    fn test_keep_going() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_keep_going(true);
+        let err = interpreter
+            .run("print 1;\nprint nope;\nprint 2;\nprint -\"a\";\nprint 3;")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Runtime error(s):\n\
+             [ line 2 ] : Undefined variable 'nope'.\n\
+             [ line 4 ] : Operand must be a number.\n"
+        );
+        assert_eq!(interpreter.get_output(), "1\n2\n3\n");
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let interpreter = Interpreter::new();
+        // test threads have a small stack, so the limit is lowered
+        interpreter.set_max_call_depth(50);
+        let err = interpreter
+            .run("fun f(n) { return 1 + f(n + 1); }\nprint \"before\";\nf(0);")
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("Runtime error: [ line 1 ] : Stack overflow.\n"));
+        assert!(message.ends_with("    ... 40 more\n"));
+        assert_eq!(interpreter.get_output(), "before\n");
+
+        // the stack is unwound after the error
+        let interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(3);
+        interpreter
+            .run("fun f(n) { if (n > 0) f(n - 1); } f(2);")
+            .unwrap();
+        assert!(interpreter.run("f(3);").is_err());
+        interpreter.run("f(2);").unwrap();
+    }
+
+    #[test]
+    fn test_run_with_limit() {
+        let interpreter = Interpreter::new();
+        let err = interpreter
+            .run_with_limit("print \"start\";\nwhile (true) {}", 1000)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Runtime error: [ line 2 ] : Execution limit exceeded.\n"
+        );
+
+        // tail calls don't grow the stack, but each one is a step
+        let err = interpreter
+            .run_with_limit("fun f() { return f(); }\nf();", 1000)
+            .unwrap_err();
+        assert!(err.to_string().contains("Execution limit exceeded."));
+
+        // programs within the limit finish, and the limit only applies to that run
+        interpreter
+            .run_with_limit("for (var i = 0; i < 10; i = i + 1) {}", 11)
+            .unwrap();
+        interpreter
+            .run("for (var i = 0; i < 2000; i = i + 1) {} print \"done\";")
+            .unwrap();
+        assert_eq!(interpreter.get_output(), "start\ndone\n");
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let interpreter = Interpreter::new();
+        let interrupted = interpreter.interrupt_flag();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            interrupted.store(true, Ordering::Relaxed);
+        });
+        let err = interpreter
+            .run("print \"start\";\nwhile (true) {}")
+            .unwrap_err();
+        handle.join().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Runtime error: [ line 2 ] : Interrupted.\n"
+        );
+
+        // the flag was cleared, so the next program runs to the end
+        interpreter
+            .run("for (var i = 0; i < 10; i = i + 1) {}")
+            .unwrap();
+        assert_eq!(interpreter.get_output(), "start\n");
+    }
+
+    #[test]
+    fn test_cycles_are_collected() {
+        let interpreter = Interpreter::new();
+        interpreter
+            .run(
+                "class Node { init() { this.me = this; } }
+                var kept = Node();
+                kept.other = Node();",
+            )
+            .unwrap();
+        for _ in 0..1000 {
+            interpreter
+                .run(
+                    "{
+                        var a = Node();
+                        var b = Node();
+                        a.next = b;
+                        b.next = a;
+                        b.get = fun () { return a; };
+                    }",
+                )
+                .unwrap();
+        }
+        // collections ran while the nodes were created, they don't pile up
+        assert!(interpreter.ctx.instances.borrow().len() < 2000);
+        collect(&interpreter.ctx);
+        // the two kept nodes and `Math`
+        assert_eq!(interpreter.ctx.instances.borrow().len(), 3);
+
+        // the value of the last statement is kept alive as well
+        let node = interpreter.eval_str("Node();").unwrap();
+        collect(&interpreter.ctx);
+        assert_eq!(interpreter.ctx.instances.borrow().len(), 4);
+        drop(node);
+        interpreter
+            .run("print kept.me.me; print kept.other.me;")
+            .unwrap();
+        collect(&interpreter.ctx);
+        assert_eq!(interpreter.ctx.instances.borrow().len(), 3);
+        assert_eq!(interpreter.get_output(), "Node instance\nNode instance\n");
+    }
+
+    #[test]
+    fn test_warnings() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .run("var a = 1; { var a = 2; { var a = 3; } }")
+            .unwrap();
+        assert!(interpreter.take_warnings().is_empty());
+
+        interpreter.set_warn_shadowing(true);
+        interpreter
+            .run("{ var b = 1; { var b = 2; print b; } }")
+            .unwrap();
+        let warnings: Vec<_> = interpreter
+            .take_warnings()
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            ["[ line 1 ] : Variable 'b' shadows an outer binding."]
+        );
+        assert!(interpreter.take_warnings().is_empty());
+        assert_eq!(interpreter.get_output(), "2\n");
+    }
+
+    #[test]
+    fn test_const_across_runs() {
+        let interpreter = Interpreter::new();
+        interpreter.run("const x = 1;").unwrap();
+        let err = interpreter.run("x = 2;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Runtime error: [ line 1 ] : Cannot assign to constant 'x'.\n"
+        );
+        interpreter.run("var x = 3; x = 4; print x;").unwrap();
+        assert_eq!(interpreter.get_output(), "4\n");
+    }
+
+    #[test]
+    fn test_get_field_does_not_intern() {
+        let interpreter = Interpreter::new();
+        interpreter
+            .run("class A {} print getField(A(), \"neverUsedAsAName\");")
+            .unwrap();
+        assert!(interpreter.get_global("neverUsedAsAName").is_none());
+        assert_eq!(Symbol::get("neverUsedAsAName"), None);
+        assert_eq!(interpreter.get_output(), "nil\n");
+    }
+
+    #[test]
+    fn test_values_held_by_the_host_are_kept() {
+        let interpreter = Interpreter::new();
+        let node = interpreter
+            .eval_str(
+                "class Node { init() { this.me = this; } }
+                fun make() { var n = Node(); n.value = 42; return n; }
+                make();",
+            )
+            .unwrap();
+        // the node isn't reachable from the globals, only the host has it
+        collect(&interpreter.ctx);
+        let LoxType::Instance(instance) = &node else {
+            panic!("expected an instance, got {node}");
+        };
+        assert_eq!(
+            instance.borrow().field(&Symbol::intern("value")),
+            Some(LoxType::Number(42.0))
+        );
+
+        interpreter.set_global("held", node.clone());
+        interpreter.run("print held.me.value;").unwrap();
+        assert_eq!(interpreter.get_output(), "42\n");
+    }
+
+    #[test]
+    fn test_long_running_program() {
+        let interpreter = Interpreter::new();
+        interpreter
+            .run(
+                "class N {}
+                var kept = [];
+                for (var i = 0; i < 100000; i = i + 1) {
+                    var n = N();
+                    if (i % 1000 == 0) kept.push(n);
+                }",
+            )
+            .unwrap();
+        // freed instances don't pile up in the list of tracked ones
+        let tracked = interpreter.ctx.instances.borrow().len();
+        assert!(tracked <= 2 * cycles::MIN_THRESHOLD, "{tracked} tracked");
+        interpreter.run("print len(kept);").unwrap();
+        assert_eq!(interpreter.get_output(), "100\n");
+    }
+
+    #[test]
+    fn test_cycles_are_collected_while_running() {
+        let interpreter = Interpreter::new();
+        interpreter
+            .run(
+                "class Pair {}
+                fun link() {
+                    var a = Pair();
+                    var b = Pair();
+                    a.other = b;
+                    b.other = a;
+                    a.get = fun () { return b; };
+                    return a;
+                }
+                var last;
+                for (var i = 0; i < 20000; i = i + 1) {
+                    last = link();
+                }
+                print last.get().other == last;",
+            )
+            .unwrap();
+        let tracked = interpreter.ctx.instances.borrow().len();
+        assert!(tracked <= 2 * cycles::MIN_THRESHOLD, "{tracked} tracked");
+        assert_eq!(interpreter.get_output(), "true\n");
+    }
+
     #[test]
     fn test_interpreter() {
         glob!("../../test_programs/interpreter/", "**/*.lox", |path| {
             let input = fs::read_to_string(path).unwrap();
-            let interpreter = Interpreter::new();
+            let mut interpreter = Interpreter::new();
+            interpreter.enable_file_access();
             let output = match interpreter.run(&input) {
                 Ok(_) => interpreter.get_output(),
                 Err(e) => e.to_string(),
@@ -177,4 +1184,22 @@ mod tests {
             assert_snapshot!(output);
         });
     }
+
+    #[test]
+    fn test_chained_comparisons() {
+        glob!(
+            "../../test_programs/chained_comparisons/",
+            "*.lox",
+            |path| {
+                let input = fs::read_to_string(path).unwrap();
+                let mut interpreter = Interpreter::new();
+                interpreter.set_chained_comparisons(true);
+                let output = match interpreter.run(&input) {
+                    Ok(_) => interpreter.get_output(),
+                    Err(e) => e.to_string(),
+                };
+                assert_snapshot!(output);
+            }
+        );
+    }
 }
@@ -0,0 +1,38 @@
+//! A tree-walking interpreter for Lox. The `rlox` binary is a thin command line around it;
+//! other programs can embed an [`Interpreter`], define natives for it and exchange values
+//! with the scripts it runs.
+
+mod ast;
+mod error;
+mod interpreter;
+mod loxtype;
+mod native_fns;
+mod parser;
+mod resolver;
+mod scanner;
+mod symbol;
+mod token;
+
+use itertools::Itertools;
+
+pub use error::{Error, ErrorDetail};
+pub use interpreter::{Interpreter, DEFAULT_MAX_CALL_DEPTH};
+pub use loxtype::{LoxCallable, LoxType};
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Parses and resolves the program and formats its statements, so the slots the resolver
+/// assigned show up too.
+pub fn dump_ast(source: &str, chained_comparisons: bool) -> Result<String> {
+    let tokens = scanner::scan_tokens(source)?;
+    let mut statements = parser::Parser::new(&tokens)
+        .with_chained_comparisons(chained_comparisons)
+        .parse()?;
+    resolver::resolve(&mut statements)?;
+    Ok(format!("{statements:#?}"))
+}
+
+/// One line per token of the program, see `Token`'s `Display`.
+pub fn dump_tokens(source: &str) -> Result<String> {
+    let tokens = scanner::scan_tokens(source)?;
+    Ok(tokens.iter().join("\n"))
+}
@@ -1,111 +1,284 @@
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{Debug, Display},
+    hash::{Hash, Hasher},
     rc::Rc,
 };
 
+use itertools::Itertools;
+
 use crate::{
-    ast::{ClassStatement, FunctionStatement, Statement},
+    ast::{ClassStatement, FunctionExpression, FunctionStatement, Parameter, Slot, Statement},
     error::{Error, ErrorDetail},
-    interpreter::{run_block, Context, StatementResult},
+    interpreter::{call_value, check_arity, run_block, Context, Marker, StatementResult},
+    symbol::Symbol,
     Result,
 };
 
 pub trait LoxCallable: Debug + Display {
     fn arity(&self) -> usize;
+
+    /// Callables with optional trailing arguments accept up to this many arguments.
+    fn max_arity(&self) -> usize {
+        self.arity()
+    }
+
     fn call(&self, arguments: Vec<LoxType>) -> Result<LoxType>;
+
+    /// Called with the context of the call site. Natives that need to do IO or see globals
+    /// override this, everything else only implements `call`.
+    fn call_with_ctx(&self, _ctx: Context, arguments: Vec<LoxType>) -> Result<LoxType> {
+        self.call(arguments)
+    }
+
+    /// Functions written in Lox return themselves, so tail calls to them can be run in a loop.
+    fn as_function(&self) -> Option<&LoxFunction> {
+        None
+    }
+
+    /// Names of the parameters that keyword arguments can be matched against, without a rest
+    /// parameter. Natives have none.
+    fn parameter_names(&self) -> Option<&[Symbol]> {
+        None
+    }
+
+    /// Marks the values this callable keeps alive, see `Marker`.
+    fn trace(&self, _marker: &mut Marker) {}
 }
 
+/// The parts of a function that don't depend on what it closes over. Closures and bound
+/// methods made from the same declaration share them.
 #[derive(Debug)]
-pub struct LoxFunction {
-    name: String,
-    parameters: Vec<String>,
+struct FunctionDeclaration {
+    maybe_name: Option<Symbol>,
+    parameters: Vec<Symbol>,
     statements: Rc<Vec<Box<dyn Statement>>>,
+    is_getter: bool,
+    /// The last parameter is a rest parameter.
+    is_variadic: bool,
+}
+
+impl FunctionDeclaration {
+    fn from_statement(stmt: &FunctionStatement) -> Rc<Self> {
+        Rc::new(Self {
+            maybe_name: Some(stmt.name.clone()),
+            parameters: stmt.parameters.iter().map(|p| p.name.clone()).collect(),
+            statements: stmt.statements.clone(),
+            is_getter: stmt.is_getter,
+            is_variadic: is_variadic(&stmt.parameters),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct LoxFunction {
+    declaration: Rc<FunctionDeclaration>,
     is_initializer: bool,
     ctx: Context,
 }
 
 impl LoxFunction {
-    pub fn from_statement(
-        stmt: &FunctionStatement,
-        ctx: Context,
-        bind_this: Option<LoxType>,
-    ) -> Self {
-        let is_initializer = bind_this.is_some() && stmt.name == "init";
-        let fn_ctx = if let Some(object) = bind_this {
-            let child_ctx = ctx.new_child_ctx();
-            child_ctx.define("this", object);
-            child_ctx
-        } else {
-            ctx
-        };
+    pub fn from_statement(stmt: &FunctionStatement, ctx: Context) -> Self {
+        Self {
+            declaration: FunctionDeclaration::from_statement(stmt),
+            is_initializer: false,
+            ctx,
+        }
+    }
 
+    pub fn from_expression(expr: &FunctionExpression, ctx: Context) -> Self {
         Self {
-            name: stmt.name.clone(),
-            parameters: stmt.parameters.iter().map(|p| p.name.clone()).collect(),
-            statements: stmt.statements.clone(),
+            declaration: Rc::new(FunctionDeclaration {
+                maybe_name: None,
+                parameters: expr.parameters.iter().map(|p| p.name.clone()).collect(),
+                statements: expr.statements.clone(),
+                is_getter: false,
+                is_variadic: is_variadic(&expr.parameters),
+            }),
+            is_initializer: false,
+            ctx,
+        }
+    }
+
+    /// Makes a method callable on `this`.
+    fn bind(declaration: &Rc<FunctionDeclaration>, ctx: &Context, this: LoxType) -> Self {
+        let is_initializer = declaration.maybe_name.as_deref() == Some("init");
+        let method_ctx = ctx.new_child_ctx();
+        method_ctx.define(&Symbol::intern("this"), this);
+        Self {
+            declaration: declaration.clone(),
             is_initializer,
-            ctx: fn_ctx,
+            ctx: method_ctx,
+        }
+    }
+
+    fn run(&self, mut arguments: Vec<LoxType>) -> Result<StatementResult> {
+        if self.declaration.is_variadic {
+            let rest = arguments.split_off(self.declaration.parameters.len() - 1);
+            arguments.push(LoxType::list(rest));
+        }
+        run_block(
+            self.ctx.clone(),
+            &self.declaration.statements,
+            Some((&self.declaration.parameters, arguments)),
+        )
+        .map_err(|e| e.with_trace(|| self.ctx.stack_trace()))
+    }
+
+    fn finish(&self, block_res: StatementResult) -> Result<LoxType> {
+        if self.is_initializer {
+            let this = Slot {
+                distance: 0,
+                index: 0,
+            };
+            Ok(self.ctx.get_local(this))
+        } else {
+            match block_res {
+                StatementResult::Void
+                | StatementResult::Value(_)
+                | StatementResult::Break
+                | StatementResult::Continue => Ok(LoxType::Nil),
+                StatementResult::Return(r) => Ok(r),
+                StatementResult::TailCall(_) => unreachable!("tail calls are made by `call`"),
+            }
+        }
+    }
+
+    /// Turns a looked up method into the value of the property access: getters are run
+    /// immediately, everything else is returned as a callable.
+    pub fn into_property(self) -> Result<LoxType> {
+        if self.declaration.is_getter {
+            self.call(vec![])
+        } else {
+            Ok(LoxType::Callable(Rc::new(self)))
         }
     }
 }
 
 impl Display for LoxFunction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<fn \"{}\">", self.name)
+        match &self.declaration.maybe_name {
+            Some(name) => write!(f, "<fn \"{}\">", name),
+            None => write!(f, "<anonymous fn>"),
+        }
     }
 }
 
 impl LoxCallable for LoxFunction {
     fn arity(&self) -> usize {
-        self.parameters.len()
+        if self.declaration.is_variadic {
+            self.declaration.parameters.len() - 1
+        } else {
+            self.declaration.parameters.len()
+        }
     }
 
-    fn call(&self, arguments: Vec<LoxType>) -> Result<LoxType> {
-        let block_res = run_block(
-            self.ctx.clone(),
-            &self.statements,
-            Some((&self.parameters, arguments)),
-        )?;
-        if self.is_initializer {
-            Ok(self.ctx.get_at(Some(0), "this").unwrap())
+    fn max_arity(&self) -> usize {
+        if self.declaration.is_variadic {
+            usize::MAX
         } else {
-            match block_res {
-                StatementResult::Void => Ok(LoxType::Nil),
-                StatementResult::Return(r) => Ok(r),
+            self.arity()
+        }
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> Result<LoxType> {
+        // a tail call to another Lox function replaces the running one
+        let mut maybe_tail_callee: Option<Rc<dyn LoxCallable>> = None;
+        let mut arguments = arguments;
+        loop {
+            let function = maybe_tail_callee
+                .as_deref()
+                .and_then(|callee| callee.as_function())
+                .unwrap_or(self);
+            let trace = || function.ctx.stack_trace();
+            match function.run(arguments)? {
+                StatementResult::TailCall(call) => match &call.callee {
+                    LoxType::Callable(callee) if callee.as_function().is_some() => {
+                        check_arity(callee.as_ref(), call.arguments.len(), call.line)
+                            .and_then(|_| function.ctx.step(call.line))
+                            .map_err(|e| e.with_trace(trace))?;
+                        arguments = call.arguments;
+                        maybe_tail_callee = Some(callee.clone());
+                    }
+                    _ => {
+                        return call_value(
+                            function.ctx.clone(),
+                            call.callee,
+                            call.arguments,
+                            call.line,
+                        )
+                        .map_err(|e| e.with_trace(trace))
+                    }
+                },
+                result => return function.finish(result),
             }
         }
     }
+
+    fn as_function(&self) -> Option<&LoxFunction> {
+        Some(self)
+    }
+
+    fn parameter_names(&self) -> Option<&[Symbol]> {
+        Some(&self.declaration.parameters[..self.arity()])
+    }
+
+    fn trace(&self, marker: &mut Marker) {
+        marker.mark_ctx(&self.ctx);
+    }
 }
 
 #[derive(Debug)]
 pub struct LoxInstance {
     class: Rc<LoxClass>,
-    fields: HashMap<String, LoxType>,
+    fields: HashMap<Symbol, LoxType>,
 }
 
 impl LoxInstance {
     fn new(class: Rc<LoxClass>) -> LoxType {
-        LoxType::Instance(Rc::new(RefCell::new(Self {
+        let instance = Rc::new(RefCell::new(Self {
             class: class.clone(),
             fields: HashMap::new(),
-        })))
+        }));
+        class.ctx.track_instance(&instance);
+        LoxType::Instance(instance)
     }
 
-    pub fn get(instance: Rc<RefCell<LoxInstance>>, name: &str, line: u32) -> Result<LoxType> {
-        if let Some(field) = instance.borrow().fields.get(name) {
-            return Ok(field.clone());
+    pub fn trace(&self, marker: &mut Marker) {
+        marker.mark_class(&self.class);
+        for value in self.fields.values() {
+            marker.mark_value(value);
         }
+    }
 
-        instance
-            .borrow()
-            .class
-            .get_method(name, LoxType::Instance(instance.clone()), line)
-            .map(|m| LoxType::Callable(Rc::new(m)))
+    pub fn class(&self) -> &Rc<LoxClass> {
+        &self.class
+    }
+
+    /// Empties the instance, used to break reference cycles.
+    pub fn take_fields(&mut self) -> HashMap<Symbol, LoxType> {
+        std::mem::take(&mut self.fields)
+    }
+
+    pub fn field(&self, name: &Symbol) -> Option<LoxType> {
+        self.fields.get(name).cloned()
+    }
+
+    pub fn get(instance: Rc<RefCell<LoxInstance>>, name: &Symbol, line: u32) -> Result<LoxType> {
+        if let Some(field) = instance.borrow().field(name) {
+            return Ok(field);
+        }
+
+        let method =
+            instance
+                .borrow()
+                .class
+                .get_method(name, LoxType::Instance(instance.clone()), line)?;
+        method.into_property()
     }
 
-    pub fn set(instance: Rc<RefCell<LoxInstance>>, name: &str, value: LoxType) -> LoxType {
+    pub fn set(instance: Rc<RefCell<LoxInstance>>, name: &Symbol, value: LoxType) -> LoxType {
         instance
             .borrow_mut()
             .fields
@@ -114,6 +287,14 @@ impl LoxInstance {
     }
 }
 
+/// Build with `--features trace-drops` to see when instances are freed.
+#[cfg(feature = "trace-drops")]
+impl Drop for LoxInstance {
+    fn drop(&mut self) {
+        eprintln!("[drop] {} instance", self.class.name);
+    }
+}
+
 impl Display for LoxInstance {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} instance", self.class.name)
@@ -124,8 +305,13 @@ impl Display for LoxInstance {
 pub struct LoxClass {
     pub name: String,
     maybe_superclass: Option<Rc<LoxClass>>,
-    methods: Rc<HashMap<String, FunctionStatement>>,
+    methods: HashMap<Symbol, Rc<FunctionDeclaration>>,
+    static_methods: HashMap<Symbol, Rc<FunctionDeclaration>>,
     ctx: Context,
+    /// Static methods don't see `super`, so they close over the enclosing context directly.
+    static_ctx: Context,
+    /// Provided by the interpreter rather than declared by a program, like the class of `Math`.
+    pub is_native: bool,
 }
 
 impl LoxClass {
@@ -136,46 +322,88 @@ impl LoxClass {
     ) -> Self {
         let class_ctx = if let Some(superclass) = &maybe_superclass {
             let child_ctx = ctx.new_child_ctx();
-            child_ctx.define("super", LoxType::Class(superclass.clone()));
+            child_ctx.define(&Symbol::intern("super"), LoxType::Class(superclass.clone()));
             child_ctx
         } else {
-            ctx
+            ctx.clone()
         };
 
         Self {
-            name: stmt.name.clone(),
+            name: stmt.name.to_string(),
             maybe_superclass,
-            methods: stmt.methods.clone(),
+            methods: declarations(&stmt.methods),
+            static_methods: declarations(&stmt.static_methods),
             ctx: class_ctx,
+            static_ctx: ctx,
+            is_native: false,
+        }
+    }
+
+    /// A class without methods whose instances the interpreter fills with natives.
+    pub fn native(name: &str, ctx: Context) -> Self {
+        Self {
+            name: name.to_owned(),
+            maybe_superclass: None,
+            methods: HashMap::new(),
+            static_methods: HashMap::new(),
+            ctx: ctx.clone(),
+            static_ctx: ctx,
+            is_native: true,
+        }
+    }
+
+    pub fn trace(&self, marker: &mut Marker) {
+        marker.mark_ctx(&self.ctx);
+        marker.mark_ctx(&self.static_ctx);
+        if let Some(superclass) = &self.maybe_superclass {
+            marker.mark_class(superclass);
         }
     }
 
+    /// True for the class itself and every class inheriting from it.
+    pub fn is_subclass_of(&self, other: &LoxClass) -> bool {
+        std::ptr::eq(self, other)
+            || self
+                .maybe_superclass
+                .as_ref()
+                .is_some_and(|superclass| superclass.is_subclass_of(other))
+    }
+
     pub fn instantiate(self: Rc<Self>, init_arguments: Vec<LoxType>, line: u32) -> Result<LoxType> {
         let instance = LoxInstance::new(self.clone());
 
-        let maybe_init_method = self.get_method("init", instance.clone(), line).ok();
+        let maybe_init_method = self
+            .get_method(&Symbol::intern("init"), instance.clone(), line)
+            .ok();
 
-        let arity = maybe_init_method.as_ref().map_or(0, |i| i.arity());
-        if arity != init_arguments.len() {
+        if let Some(init_method) = maybe_init_method {
+            check_arity(&init_method, init_arguments.len(), line)?;
+            let _ = init_method.call(init_arguments)?;
+        } else if !init_arguments.is_empty() {
             return Err(Error::RuntimeError(ErrorDetail::new(
                 line,
-                format!(
-                    "Expected {} arguments but got {}.",
-                    arity,
-                    init_arguments.len()
-                ),
+                format!("Expected 0 arguments but got {}.", init_arguments.len()),
             )));
         }
+        Ok(instance)
+    }
 
-        if let Some(init_method) = maybe_init_method {
-            let _ = init_method.call(init_arguments)?;
+    /// The parameters of `init`, which may be inherited. Keyword arguments of a call to the
+    /// class are matched against them.
+    pub fn init_parameter_names(&self) -> &[Symbol] {
+        match self.methods.get(&Symbol::intern("init")) {
+            Some(init) if init.is_variadic => &init.parameters[..init.parameters.len() - 1],
+            Some(init) => &init.parameters,
+            None => self
+                .maybe_superclass
+                .as_ref()
+                .map_or(&[], |superclass| superclass.init_parameter_names()),
         }
-        Ok(instance)
     }
 
-    pub fn get_method(&self, name: &str, this: LoxType, line: u32) -> Result<LoxFunction> {
-        if let Some(f) = self.methods.get(name) {
-            Ok(LoxFunction::from_statement(f, self.ctx.clone(), Some(this)))
+    pub fn get_method(&self, name: &Symbol, this: LoxType, line: u32) -> Result<LoxFunction> {
+        if let Some(declaration) = self.methods.get(name) {
+            Ok(LoxFunction::bind(declaration, &self.ctx, this))
         } else {
             self.maybe_superclass.as_ref().map_or_else(
                 || {
@@ -188,6 +416,42 @@ impl LoxClass {
             )
         }
     }
+
+    pub fn get_static_method(&self, name: &Symbol, line: u32) -> Result<LoxFunction> {
+        if let Some(declaration) = self.static_methods.get(name) {
+            Ok(LoxFunction {
+                declaration: declaration.clone(),
+                is_initializer: false,
+                ctx: self.static_ctx.clone(),
+            })
+        } else {
+            self.maybe_superclass.as_ref().map_or_else(
+                || {
+                    Err(Error::RuntimeError(ErrorDetail::new(
+                        line,
+                        format!("Undefined property '{}'.", name),
+                    )))
+                },
+                |sc| sc.get_static_method(name, line),
+            )
+        }
+    }
+}
+
+fn is_variadic(parameters: &[Parameter]) -> bool {
+    parameters.last().is_some_and(|parameter| parameter.is_rest)
+}
+
+fn declarations(methods: &[FunctionStatement]) -> HashMap<Symbol, Rc<FunctionDeclaration>> {
+    methods
+        .iter()
+        .map(|method| {
+            (
+                method.name.clone(),
+                FunctionDeclaration::from_statement(method),
+            )
+        })
+        .collect()
 }
 
 impl Display for LoxClass {
@@ -204,6 +468,8 @@ pub enum LoxType {
     Callable(Rc<dyn LoxCallable>),
     Class(Rc<LoxClass>),
     Instance(Rc<RefCell<LoxInstance>>),
+    List(Rc<RefCell<Vec<LoxType>>>),
+    Map(Rc<RefCell<LoxMap>>),
     Nil,
 }
 
@@ -217,10 +483,57 @@ impl LoxType {
             LoxType::Callable(_) => true,
             LoxType::Class(_) => true,
             LoxType::Instance(_) => true,
+            LoxType::List(_) => true,
+            LoxType::Map(_) => true,
         }
     }
+
+    pub fn list(elements: Vec<LoxType>) -> Self {
+        LoxType::List(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            LoxType::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            LoxType::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<f64> for LoxType {
+    fn from(n: f64) -> Self {
+        LoxType::Number(n)
+    }
 }
 
+impl From<bool> for LoxType {
+    fn from(b: bool) -> Self {
+        LoxType::Boolean(b)
+    }
+}
+
+impl From<String> for LoxType {
+    fn from(s: String) -> Self {
+        LoxType::String(s)
+    }
+}
+
+impl From<&str> for LoxType {
+    fn from(s: &str) -> Self {
+        LoxType::String(s.to_owned())
+    }
+}
+
+/// Values are compared like in the book: numbers, strings, booleans and nil by value,
+/// everything else by identity. Numbers follow IEEE 754, so `NaN` isn't equal to anything,
+/// not even itself.
 impl PartialEq for LoxType {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -230,6 +543,9 @@ impl PartialEq for LoxType {
             (LoxType::Nil, LoxType::Nil) => true,
             (LoxType::Callable(l), LoxType::Callable(r)) => Rc::ptr_eq(l, r),
             (LoxType::Class(l), LoxType::Class(r)) => Rc::ptr_eq(l, r),
+            (LoxType::Instance(l), LoxType::Instance(r)) => Rc::ptr_eq(l, r),
+            (LoxType::List(l), LoxType::List(r)) => Rc::ptr_eq(l, r),
+            (LoxType::Map(l), LoxType::Map(r)) => Rc::ptr_eq(l, r),
             _ => false,
         }
     }
@@ -238,7 +554,7 @@ impl PartialEq for LoxType {
 impl Display for LoxType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LoxType::Number(n) => write!(f, "{n}"),
+            LoxType::Number(n) => write!(f, "{}", format_number(*n)),
             LoxType::Boolean(b) => write!(f, "{b}"),
             LoxType::String(s) => write!(f, "{s}"),
             LoxType::Nil => write!(f, "nil"),
@@ -247,6 +563,213 @@ impl Display for LoxType {
             }
             LoxType::Class(c) => write!(f, "{c}"),
             LoxType::Instance(i) => write!(f, "{}", i.borrow()),
+            LoxType::List(l) => format_once(f, Rc::as_ptr(l).cast(), "[...]", |f| {
+                write!(f, "[{}]", l.borrow().iter().join(", "))
+            }),
+            LoxType::Map(m) => format_once(f, Rc::as_ptr(m).cast(), "{...}", |f| {
+                write!(
+                    f,
+                    "{{{}}}",
+                    m.borrow()
+                        .iter()
+                        .map(|(k, v)| format!("{k}: {v}"))
+                        .join(", ")
+                )
+            }),
+        }
+    }
+}
+
+thread_local! {
+    /// Lists and maps being formatted right now.
+    static FORMATTING: RefCell<HashSet<*const ()>> = RefCell::new(HashSet::new());
+}
+
+/// Formats a list or map with `format`, unless it's already being formatted further up, because it
+/// contains itself. Then `placeholder` is written instead of recursing forever.
+fn format_once(
+    f: &mut std::fmt::Formatter<'_>,
+    ptr: *const (),
+    placeholder: &str,
+    format: impl FnOnce(&mut std::fmt::Formatter<'_>) -> std::fmt::Result,
+) -> std::fmt::Result {
+    if !FORMATTING.with(|formatting| formatting.borrow_mut().insert(ptr)) {
+        return write!(f, "{placeholder}");
+    }
+    let result = format(f);
+    FORMATTING.with(|formatting| formatting.borrow_mut().remove(&ptr));
+    result
+}
+
+/// Significant digits of numbers that aren't printed as integers, like `%g` in clox.
+const PRECISION: usize = 6;
+
+/// Whole numbers are printed with all their digits and no decimal point as long as they are
+/// exact, everything else like clox's `printf("%g")`: rounded to six significant digits,
+/// without trailing zeros, and in scientific notation when very large or small.
+fn format_number(n: f64) -> String {
+    if !n.is_finite() || (n.fract() == 0.0 && n.abs() < 1e16) {
+        return format!("{n}");
+    }
+    // formatting in scientific notation first rounds to the precision, which can change the
+    // exponent, like 999999.5 becoming 1.00000e6
+    let scientific = format!("{:.*e}", PRECISION - 1, n);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if exponent < -4 || exponent >= PRECISION as i32 {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!(
+            "{}e{sign}{:02}",
+            trim_fraction(mantissa),
+            exponent.unsigned_abs()
+        )
+    } else {
+        let decimals = (PRECISION as i32 - 1 - exponent) as usize;
+        trim_fraction(&format!("{n:.decimals$}")).to_owned()
+    }
+}
+
+fn trim_fraction(digits: &str) -> &str {
+    if digits.contains('.') {
+        digits.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        digits
+    }
+}
+
+/// Key of a map entry. Strings, numbers, functions and classes can be used as keys. Numbers
+/// are stored by their bit pattern so they can be hashed, functions and classes are compared
+/// and hashed by identity, like `==` compares them.
+#[derive(Debug, Clone)]
+pub enum MapKey {
+    Number(u64),
+    String(String),
+    Callable(Rc<dyn LoxCallable>),
+    Class(Rc<LoxClass>),
+}
+
+impl MapKey {
+    pub fn from_value(value: LoxType, line: u32) -> Result<Self> {
+        match value {
+            LoxType::Number(n) => {
+                // 0.0 and -0.0 compare equal, so they have to be the same key
+                let n = if n == 0.0 { 0.0 } else { n };
+                Ok(MapKey::Number(n.to_bits()))
+            }
+            LoxType::String(s) => Ok(MapKey::String(s)),
+            LoxType::Callable(callable) => Ok(MapKey::Callable(callable)),
+            LoxType::Class(class) => Ok(MapKey::Class(class)),
+            _ => Err(Error::RuntimeError(ErrorDetail::new(
+                line,
+                "Map keys must be strings, numbers, functions or classes.",
+            ))),
+        }
+    }
+
+    pub fn to_value(&self) -> LoxType {
+        match self {
+            MapKey::Number(bits) => LoxType::Number(f64::from_bits(*bits)),
+            MapKey::String(s) => LoxType::String(s.clone()),
+            MapKey::Callable(callable) => LoxType::Callable(callable.clone()),
+            MapKey::Class(class) => LoxType::Class(class.clone()),
+        }
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (MapKey::Number(l), MapKey::Number(r)) => l == r,
+            (MapKey::String(l), MapKey::String(r)) => l == r,
+            (MapKey::Callable(l), MapKey::Callable(r)) => Rc::ptr_eq(l, r),
+            (MapKey::Class(l), MapKey::Class(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for MapKey {}
+
+impl Hash for MapKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            MapKey::Number(bits) => bits.hash(state),
+            MapKey::String(s) => s.hash(state),
+            MapKey::Callable(callable) => Rc::as_ptr(callable).cast::<()>().hash(state),
+            MapKey::Class(class) => Rc::as_ptr(class).hash(state),
+        }
+    }
+}
+
+impl Display for MapKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_value())
+    }
+}
+
+/// The entries of a map in the order their keys were first inserted, so printing and
+/// iterating a map gives the same result on every run.
+#[derive(Debug, Default)]
+pub struct LoxMap {
+    entries: Vec<(MapKey, LoxType)>,
+    /// Position of each key in `entries`.
+    positions: HashMap<MapKey, usize>,
+}
+
+impl LoxMap {
+    pub fn get(&self, key: &MapKey) -> Option<&LoxType> {
+        self.positions.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    /// Overwriting the value of a key keeps its position.
+    pub fn insert(&mut self, key: MapKey, value: LoxType) {
+        match self.positions.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
         }
     }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&MapKey, &LoxType)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &MapKey> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &LoxType> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(LoxType::from(1.5), LoxType::Number(1.5));
+        assert_eq!(LoxType::from(true), LoxType::Boolean(true));
+        assert_eq!(
+            LoxType::from("lox".to_owned()),
+            LoxType::String("lox".to_owned())
+        );
+        assert_eq!(LoxType::from("lox"), LoxType::String("lox".to_owned()));
+
+        let list = LoxType::list(vec![1.0.into(), "two".into()]);
+        assert_eq!(list.to_string(), "[1, two]");
+
+        assert_eq!(LoxType::from(2.0).as_number(), Some(2.0));
+        assert_eq!(LoxType::from("2").as_number(), None);
+        assert_eq!(LoxType::from("text").as_string(), Some("text"));
+        assert_eq!(LoxType::Nil.as_string(), None);
+    }
 }
@@ -1,41 +1,163 @@
-mod ast;
-mod error;
-mod interpreter;
-mod loxtype;
-mod native_fns;
-mod parser;
-mod resolver;
-mod scanner;
-mod token;
-
 use std::fs;
+use std::io::{self, IsTerminal, Read};
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::thread;
 
 use anyhow::anyhow;
-use clap::Parser as ClapParser;
+use clap::{Parser as ClapParser, ValueEnum};
+use itertools::Itertools;
 use rustyline::{error::ReadlineError, DefaultEditor};
 
-use interpreter::Interpreter;
-pub(crate) use loxtype::{LoxCallable, LoxType};
-pub(crate) type Result<T> = std::result::Result<T, error::Error>;
+use rlox::{dump_ast, dump_tokens, Error, Interpreter, LoxType, Result, DEFAULT_MAX_CALL_DEPTH};
 
 #[derive(ClapParser)]
 struct Cli {
     source_file: Option<PathBuf>,
+    /// How errors are reported
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    format: Format,
+    /// Continue with the next top-level statement after a runtime error
+    #[arg(long)]
+    keep_going: bool,
+    /// Warn about local variables that shadow a variable of an enclosing scope
+    #[arg(long)]
+    warn_shadowing: bool,
+    /// Allow chained comparisons like `0 <= x < 10`, each operand is evaluated once
+    #[arg(long)]
+    chained_comparisons: bool,
+    /// How deeply calls can nest before a stack overflow error
+    #[arg(long, default_value_t = DEFAULT_MAX_CALL_DEPTH)]
+    max_call_depth: usize,
+    /// Stop the program after this many loop iterations and calls
+    #[arg(long)]
+    max_steps: Option<u64>,
+    /// Print the syntax tree of the program instead of running it
+    #[arg(long)]
+    dump_ast: bool,
+    /// Print the tokens of the program instead of running it
+    #[arg(long, conflicts_with = "dump_ast")]
+    tokens: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Human,
+    /// The error serialized as JSON, for editors and other tools
+    Json,
+}
+
+/// Runs a line typed at the prompt and returns the value to echo, if any. Like in other
+/// REPLs, the semicolon after a final expression may be left out. A line that fails doesn't
+/// define or assign any globals.
+fn eval_line(interpreter: &Interpreter, line: &str) -> Result<Option<String>> {
+    let line = line.trim_end();
+    // source that doesn't parse isn't run, so trying it twice has no side effects
+    let value = match interpreter.eval_str_or_rollback(line) {
+        Err(Error::IncompleteInput(detail)) if !line.ends_with([';', '}']) => {
+            match interpreter.eval_str_or_rollback(&format!("{line};")) {
+                // more than the semicolon is missing, like in `{ 1 +`
+                Err(Error::SyntaxErrors(_) | Error::IncompleteInput(_)) => {
+                    return Err(Error::IncompleteInput(detail))
+                }
+                result => result?,
+            }
+        }
+        result => result?,
+    };
+    match value {
+        LoxType::Nil => Ok(None),
+        value => Ok(Some(value.to_string())),
+    }
+}
+
+/// Runs a line typed at the prompt and prints its value. Errors are reported without ending
+/// the REPL, only `exit()` and input that needs more lines are passed on.
+fn respond(interpreter: &Interpreter, line: &str) -> Result<()> {
+    let result = eval_line(interpreter, line);
+    print_warnings(interpreter);
+    match result {
+        Ok(Some(value)) => println!("{value}"),
+        Ok(None) => (),
+        Err(error @ (Error::Exit(_) | Error::IncompleteInput(_))) => return Err(error),
+        Err(error) => eprint!("{}", error.report(line)),
+    }
+    Ok(())
+}
+
+fn print_warnings(interpreter: &Interpreter) {
+    for warning in interpreter.take_warnings() {
+        eprintln!("Warning: {warning}");
+    }
 }
 
-fn run_prompt(interpreter: Interpreter) -> anyhow::Result<()> {
+const HELP: &str = "\
+:help   show this help
+:env    list the global variables and their values
+:reset  forget everything defined so far";
+
+/// Handles the REPL's own commands, which start with a colon, and returns what to print.
+/// Anything else is Lox source and gives `None`.
+fn meta_command(interpreter: &mut Interpreter, line: &str) -> Option<String> {
+    let command = line.trim();
+    if !command.starts_with(':') {
+        return None;
+    }
+    let output = match command {
+        ":help" => HELP.to_owned(),
+        ":env" => interpreter
+            .globals()
+            .iter()
+            .map(|(name, value)| format!("{name} = {value}"))
+            .join("\n"),
+        ":reset" => {
+            interpreter.reset();
+            "Cleared all definitions.".to_owned()
+        }
+        _ => format!("Unknown command '{command}', see :help."),
+    };
+    Some(output)
+}
+
+fn run_prompt(mut interpreter: Interpreter) -> anyhow::Result<()> {
     let mut rl = DefaultEditor::new()?;
+    // the editor handles Ctrl-C while reading a line, the handler only sees the ones
+    // pressed while a program runs
+    let interrupted = interpreter.interrupt_flag();
+    ctrlc::set_handler(move || interrupted.store(true, Ordering::Relaxed))?;
 
+    // lines of a statement that isn't complete yet
+    let mut pending = String::new();
     loop {
-        let readline: std::result::Result<_, _> = rl.readline("> ");
+        let prompt = if pending.is_empty() { "> " } else { "... " };
+        let readline: std::result::Result<_, _> = rl.readline(prompt);
         match readline {
             Ok(line) => {
                 rl.add_history_entry(line.as_str())?;
-                interpreter.run(&line)?;
+                if pending.is_empty() {
+                    match meta_command(&mut interpreter, &line) {
+                        Some(output) if output.is_empty() => continue,
+                        Some(output) => {
+                            println!("{output}");
+                            continue;
+                        }
+                        None => (),
+                    }
+                }
+                pending.push_str(&line);
+                pending.push('\n');
+                match respond(&interpreter, &pending) {
+                    // an empty line gives up on the statement and shows what is missing
+                    Err(Error::IncompleteInput(_)) if !line.trim().is_empty() => continue,
+                    Err(error @ Error::IncompleteInput(_)) => eprint!("{}", error.report(&pending)),
+                    result => result?,
+                }
+                pending.clear();
             }
+            // Ctrl-C discards the line being typed, only Ctrl-D leaves the REPL
             Err(ReadlineError::Interrupted) => {
-                break;
+                pending.clear();
+                continue;
             }
             Err(ReadlineError::Eof) => {
                 break;
@@ -48,16 +170,214 @@ fn run_prompt(interpreter: Interpreter) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Stack reserved for each Lox call on top of `BASE_STACK_SIZE`. A call takes a few
+/// kilobytes of Rust stack, more for every block, loop and nested expression in its body, so
+/// this leaves room for deeply nested functions.
+const STACK_PER_CALL: usize = 64 * 1024;
+const BASE_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Runs on a thread with a stack large enough for `--max-call-depth` calls, so deep
+/// recursion is reported as a stack overflow error instead of crashing the process.
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let interpreter = Interpreter::new();
+    let stack_size = cli
+        .max_call_depth
+        .checked_mul(STACK_PER_CALL)
+        .and_then(|size| size.checked_add(BASE_STACK_SIZE))
+        .ok_or_else(|| anyhow!("--max-call-depth {} is too large", cli.max_call_depth))?;
+    thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || run(cli))?
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_keep_going(cli.keep_going);
+    interpreter.set_warn_shadowing(cli.warn_shadowing);
+    interpreter.set_chained_comparisons(cli.chained_comparisons);
+    interpreter.set_max_call_depth(cli.max_call_depth);
+    interpreter.enable_file_access();
+
+    let maybe_source = match cli.source_file {
+        Some(source_file) => Some(fs::read_to_string(source_file)?),
+        // a program piped in, like `rlox < program.lox`
+        None if !io::stdin().is_terminal() => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            Some(source)
+        }
+        None => None,
+    };
+
+    let result = match &maybe_source {
+        Some(source) if cli.dump_ast => dump_ast(source, cli.chained_comparisons)
+            .map(|ast| println!("{ast}"))
+            .map_err(anyhow::Error::from),
+        Some(source) if cli.tokens => dump_tokens(source)
+            .map(|tokens| println!("{tokens}"))
+            .map_err(anyhow::Error::from),
+        Some(source) => {
+            let result = match cli.max_steps {
+                Some(max_steps) => interpreter.run_with_limit(source, max_steps),
+                None => interpreter.run(source),
+            };
+            print_warnings(&interpreter);
+            result.map_err(anyhow::Error::from)
+        }
+        None if cli.dump_ast || cli.tokens => Err(anyhow!(
+            "--dump-ast and --tokens need a source file or piped program"
+        )),
+        None => run_prompt(interpreter),
+    };
 
-    if let Some(source_file) = cli.source_file {
-        let source = fs::read_to_string(source_file)?;
-        interpreter.run(&source)?;
-    } else {
-        run_prompt(interpreter)?;
+    match result.as_ref().err().and_then(|e| e.downcast_ref()) {
+        Some(Error::Exit(code)) => std::process::exit(*code),
+        Some(error) if cli.format == Format::Json => {
+            eprintln!("{}", serde_json::to_string(error)?);
+            std::process::exit(1);
+        }
+        Some(error) => match &maybe_source {
+            Some(source) => {
+                eprint!("{}", error.report(source));
+                std::process::exit(1);
+            }
+            None => result,
+        },
+        _ => result,
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    /// Collects what the interpreter prints, so tests can look at it.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    fn new_interpreter() -> (Interpreter, Output) {
+        let output = Output::default();
+        (
+            Interpreter::new_with_writer(Box::new(output.clone())),
+            output,
+        )
+    }
+
+    #[test]
+    fn test_eval_line() {
+        let (interpreter, output) = new_interpreter();
+        assert_eq!(
+            eval_line(&interpreter, "1 + 2;").unwrap(),
+            Some("3".to_owned())
+        );
+        assert_eq!(
+            eval_line(&interpreter, "1 + 2").unwrap(),
+            Some("3".to_owned())
+        );
+        assert_eq!(eval_line(&interpreter, "var a = \"x\"").unwrap(), None);
+        assert_eq!(eval_line(&interpreter, "a").unwrap(), Some("x".to_owned()));
+        // statements print themselves and aren't echoed a second time
+        assert_eq!(eval_line(&interpreter, "print a;").unwrap(), None);
+        assert_eq!(eval_line(&interpreter, "{ 1; }").unwrap(), None);
+        assert_eq!(eval_line(&interpreter, "nil").unwrap(), None);
+        assert_eq!(eval_line(&interpreter, "").unwrap(), None);
+        assert!(matches!(
+            eval_line(&interpreter, "1 +"),
+            Err(Error::IncompleteInput(_))
+        ));
+        assert!(matches!(
+            eval_line(&interpreter, "{ print 1 +"),
+            Err(Error::IncompleteInput(_))
+        ));
+        assert!(matches!(
+            eval_line(&interpreter, "fun f() {\n  return 1"),
+            Err(Error::IncompleteInput(_))
+        ));
+        assert_eq!(
+            eval_line(&interpreter, "fun f() {\n  return 1;\n}\nf()").unwrap(),
+            Some("1".to_owned())
+        );
+        assert!(matches!(
+            eval_line(&interpreter, "1 + )"),
+            Err(Error::SyntaxErrors(_))
+        ));
+        assert_eq!(output.text(), "x\n");
+    }
+
+    #[test]
+    fn test_eval_line_rolls_back() {
+        let (interpreter, output) = new_interpreter();
+        eval_line(&interpreter, "var a = 1;").unwrap();
+        assert!(eval_line(&interpreter, "var b = 2; a = 3; print a; nope").is_err());
+        assert_eq!(eval_line(&interpreter, "a").unwrap(), Some("1".to_owned()));
+        assert!(eval_line(&interpreter, "b").is_err());
+        // output written before the error can't be taken back
+        assert_eq!(output.text(), "3\n");
+    }
+
+    #[test]
+    fn test_meta_command() {
+        let (mut interpreter, output) = new_interpreter();
+        assert_eq!(meta_command(&mut interpreter, "print 1;"), None);
+        assert_eq!(meta_command(&mut interpreter, ":help").unwrap(), HELP);
+        assert_eq!(meta_command(&mut interpreter, ":env").unwrap(), "");
+
+        interpreter
+            .run("var b = \"two\"; var a = 1; fun f() {} class C {}")
+            .unwrap();
+        assert_eq!(
+            meta_command(&mut interpreter, " :env ").unwrap(),
+            "C = C\na = 1\nb = two\nf = <fn \"f\">"
+        );
+
+        assert_eq!(
+            meta_command(&mut interpreter, ":reset").unwrap(),
+            "Cleared all definitions."
+        );
+        assert_eq!(meta_command(&mut interpreter, ":env").unwrap(), "");
+        assert!(interpreter.run("print a;").is_err());
+        // natives are defined again
+        interpreter.run("print sqrt(4);").unwrap();
+
+        assert_eq!(
+            meta_command(&mut interpreter, ":quit").unwrap(),
+            "Unknown command ':quit', see :help."
+        );
+        assert_eq!(output.text(), "2\n");
+    }
+
+    #[test]
+    fn test_respond() {
+        let (interpreter, output) = new_interpreter();
+        // errors, including interruptions, are reported and the REPL goes on
+        respond(&interpreter, "var a = 1;").unwrap();
+        respond(&interpreter, "print nope;").unwrap();
+        respond(&interpreter, "1 + )").unwrap();
+        assert!(respond(&interpreter, "{ 1 +").is_err());
+        interpreter.interrupt_flag().store(true, Ordering::Relaxed);
+        respond(&interpreter, "while (true) {}").unwrap();
+        respond(&interpreter, "print a;").unwrap();
+        assert_eq!(output.text(), "1\n");
+    }
 }
@@ -1,9 +1,17 @@
 use std::{
+    cell::{Cell, RefCell},
     fmt::Display,
+    rc::Rc,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use crate::{LoxCallable, LoxType};
+use crate::{
+    error::{Error, ErrorDetail},
+    interpreter::{call_value, Context, Marker},
+    loxtype::LoxInstance,
+    symbol::Symbol,
+    LoxCallable, LoxType,
+};
 
 #[derive(Debug)]
 pub struct Clock();
@@ -22,6 +30,934 @@ impl LoxCallable for Clock {
     fn call(&self, _arguments: Vec<LoxType>) -> crate::Result<LoxType> {
         let now = SystemTime::now();
         let elapsed = now.duration_since(UNIX_EPOCH).unwrap();
-        Ok(LoxType::Number(elapsed.as_secs() as f64))
+        Ok(LoxType::Number(elapsed.as_secs_f64()))
+    }
+}
+
+#[derive(Debug)]
+pub struct Keys();
+
+impl Display for Keys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn keys>")
+    }
+}
+
+impl LoxCallable for Keys {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        if let LoxType::Map(map) = &arguments[0] {
+            let keys = map.borrow().keys().map(|k| k.to_value()).collect();
+            Ok(LoxType::list(keys))
+        } else {
+            Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Argument must be a map.",
+            )))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Write();
+
+impl Display for Write {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn write>")
+    }
+}
+
+impl LoxCallable for Write {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, _arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        unreachable!("write needs a context")
+    }
+
+    fn call_with_ctx(&self, ctx: Context, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        match ctx.write_stdout(&arguments[0].to_string()) {
+            Ok(_) => Ok(LoxType::Nil),
+            Err(_) => Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Could not write to stdout.",
+            ))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Input();
+
+impl Display for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn input>")
+    }
+}
+
+impl LoxCallable for Input {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(&self, _arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        unreachable!("input needs a context")
+    }
+
+    fn call_with_ctx(&self, ctx: Context, _arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        Ok(ctx.read_line().map_or(LoxType::Nil, LoxType::String))
+    }
+}
+
+fn number_argument(value: &LoxType) -> crate::Result<f64> {
+    value
+        .as_number()
+        .ok_or_else(|| Error::RuntimeError(ErrorDetail::new(0, "Operand must be a number.")))
+}
+
+fn string_argument(value: &LoxType) -> crate::Result<&str> {
+    value
+        .as_string()
+        .ok_or_else(|| Error::RuntimeError(ErrorDetail::new(0, "Argument must be a string.")))
+}
+
+/// Defines a native that takes only numbers and returns a number.
+macro_rules! number_native {
+    ($type:ident, $name:literal, || $body:expr) => {
+        number_native!($type, $name, | | $body);
+    };
+    ($type:ident, $name:literal, |$($arg:ident),*| $body:expr) => {
+        #[derive(Debug)]
+        pub struct $type();
+
+        impl Display for $type {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, concat!("<native fn ", $name, ">"))
+            }
+        }
+
+        impl LoxCallable for $type {
+            fn arity(&self) -> usize {
+                <[&str]>::len(&[$(stringify!($arg)),*])
+            }
+
+            #[allow(unused_variables, unused_mut)]
+            fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+                let mut numbers = arguments.iter().map(number_argument);
+                $(let $arg = numbers.next().unwrap()?;)*
+                Ok(LoxType::Number($body))
+            }
+        }
+    };
+}
+
+number_native!(Abs, "abs", |x| x.abs());
+number_native!(Floor, "floor", |x| x.floor());
+number_native!(Ceil, "ceil", |x| x.ceil());
+number_native!(Round, "round", |x| x.round());
+number_native!(Pow, "pow", |x, y| x.powf(y));
+number_native!(Min, "min", |a, b| a.min(b));
+number_native!(Max, "max", |a, b| a.max(b));
+number_native!(Pi, "pi", || std::f64::consts::PI);
+number_native!(E, "e", || std::f64::consts::E);
+number_native!(Sin, "sin", |x| x.sin());
+number_native!(Cos, "cos", |x| x.cos());
+number_native!(Tan, "tan", |x| x.tan());
+number_native!(Asin, "asin", |x| x.asin());
+number_native!(Acos, "acos", |x| x.acos());
+number_native!(Atan, "atan", |x| x.atan());
+number_native!(Atan2, "atan2", |y, x| y.atan2(x));
+
+#[derive(Debug)]
+pub struct Sqrt();
+
+impl Display for Sqrt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn sqrt>")
+    }
+}
+
+impl LoxCallable for Sqrt {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let x = number_argument(&arguments[0])?;
+        if x < 0.0 {
+            // consistent with division by zero, don't let NaN spread through the program
+            return Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Can't take the square root of a negative number.",
+            )));
+        }
+        Ok(LoxType::Number(x.sqrt()))
+    }
+}
+
+/// `clamp(x, lo, hi)` limits `x` to the range from `lo` to `hi`.
+#[derive(Debug)]
+pub struct Clamp();
+
+impl Display for Clamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn clamp>")
+    }
+}
+
+impl LoxCallable for Clamp {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let x = number_argument(&arguments[0])?;
+        let lo = number_argument(&arguments[1])?;
+        let hi = number_argument(&arguments[2])?;
+        // `f64::clamp` panics on these, NaN bounds included
+        if lo > hi || lo.is_nan() || hi.is_nan() {
+            return Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Lower bound must not be greater than the upper bound.",
+            )));
+        }
+        Ok(LoxType::Number(x.clamp(lo, hi)))
+    }
+}
+
+#[derive(Debug)]
+pub struct Type();
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn type>")
+    }
+}
+
+impl LoxCallable for Type {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let name = match arguments[0] {
+            LoxType::Number(_) => "number",
+            LoxType::Boolean(_) => "boolean",
+            LoxType::String(_) => "string",
+            LoxType::Callable(_) => "function",
+            LoxType::Class(_) => "class",
+            LoxType::Instance(_) => "instance",
+            LoxType::List(_) => "list",
+            LoxType::Map(_) => "map",
+            LoxType::Nil => "nil",
+        };
+        Ok(LoxType::String(name.to_owned()))
+    }
+}
+
+/// Whether the value is an instance of the class or one of its subclasses.
+#[derive(Debug)]
+pub struct IsInstance();
+
+impl Display for IsInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn isInstance>")
+    }
+}
+
+impl LoxCallable for IsInstance {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let LoxType::Class(class) = &arguments[1] else {
+            return Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Second argument must be a class.",
+            )));
+        };
+        let is_instance = match &arguments[0] {
+            LoxType::Instance(instance) => instance.borrow().class().is_subclass_of(class),
+            _ => false,
+        };
+        Ok(LoxType::Boolean(is_instance))
+    }
+}
+
+fn instance_argument(value: &LoxType) -> crate::Result<&Rc<RefCell<LoxInstance>>> {
+    if let LoxType::Instance(instance) = value {
+        Ok(instance)
+    } else {
+        Err(Error::RuntimeError(ErrorDetail::new(
+            0,
+            "First argument must be an instance.",
+        )))
+    }
+}
+
+/// Reads a field whose name is only known at runtime, nil if the instance doesn't have it.
+/// Methods aren't looked up.
+#[derive(Debug)]
+pub struct GetField();
+
+impl Display for GetField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn getField>")
+    }
+}
+
+impl LoxCallable for GetField {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let instance = instance_argument(&arguments[0])?;
+        let name = string_argument(&arguments[1])?;
+        let field = Symbol::get(name).and_then(|name| instance.borrow().field(&name));
+        Ok(field.unwrap_or(LoxType::Nil))
+    }
+}
+
+/// Sets a field whose name is only known at runtime and returns the value, like an
+/// assignment.
+#[derive(Debug)]
+pub struct SetField();
+
+impl Display for SetField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn setField>")
+    }
+}
+
+impl LoxCallable for SetField {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let instance = instance_argument(&arguments[0])?;
+        let name = Symbol::intern(string_argument(&arguments[1])?);
+        Ok(LoxInstance::set(
+            instance.clone(),
+            &name,
+            arguments[2].clone(),
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct Str();
+
+impl Display for Str {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn str>")
+    }
+}
+
+impl LoxCallable for Str {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        Ok(LoxType::String(arguments[0].to_string()))
+    }
+}
+
+/// Parses a string into a number, giving nil for strings that aren't a finite number.
+#[derive(Debug)]
+pub struct Num();
+
+impl Display for Num {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn num>")
+    }
+}
+
+impl LoxCallable for Num {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        Ok(string_argument(&arguments[0])?
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .map_or(LoxType::Nil, LoxType::Number))
+    }
+}
+
+#[derive(Debug)]
+pub struct Len();
+
+impl Display for Len {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn len>")
+    }
+}
+
+impl LoxCallable for Len {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let len = match &arguments[0] {
+            LoxType::String(s) => s.chars().count(),
+            LoxType::List(l) => l.borrow().len(),
+            LoxType::Map(m) => m.borrow().len(),
+            _ => {
+                return Err(Error::RuntimeError(ErrorDetail::new(
+                    0,
+                    "Object has no length.",
+                )))
+            }
+        };
+        Ok(LoxType::Number(len as f64))
+    }
+}
+
+/// Defines a native that takes a single string and returns a string.
+macro_rules! string_native {
+    ($type:ident, $name:literal, |$arg:ident| $body:expr) => {
+        #[derive(Debug)]
+        pub struct $type();
+
+        impl Display for $type {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, concat!("<native fn ", $name, ">"))
+            }
+        }
+
+        impl LoxCallable for $type {
+            fn arity(&self) -> usize {
+                1
+            }
+
+            fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+                let $arg = string_argument(&arguments[0])?;
+                Ok(LoxType::String($body))
+            }
+        }
+    };
+}
+
+string_native!(Upper, "upper", |s| s.to_uppercase());
+string_native!(Lower, "lower", |s| s.to_lowercase());
+string_native!(Trim, "trim", |s| s.trim().to_owned());
+
+/// Strings are indexed by characters rather than bytes, so multi-byte characters count as one.
+/// Out of range starts and lengths are clamped to the string.
+#[derive(Debug)]
+pub struct Substr();
+
+impl Display for Substr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn substr>")
+    }
+}
+
+impl LoxCallable for Substr {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let s = string_argument(&arguments[0])?;
+        // float to integer casts saturate, so negative values become 0
+        let start = number_argument(&arguments[1])? as usize;
+        let len = number_argument(&arguments[2])? as usize;
+        Ok(LoxType::String(s.chars().skip(start).take(len).collect()))
+    }
+}
+
+/// Character index of the first occurrence of `needle`, -1 if there is none.
+#[derive(Debug)]
+pub struct IndexOf();
+
+impl Display for IndexOf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn indexOf>")
+    }
+}
+
+impl LoxCallable for IndexOf {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let s = string_argument(&arguments[0])?;
+        let needle = string_argument(&arguments[1])?;
+        let index = s
+            .find(needle)
+            .map_or(-1.0, |byte_index| s[..byte_index].chars().count() as f64);
+        Ok(LoxType::Number(index))
+    }
+}
+
+/// Splits a string on every occurrence of the separator, an empty separator splits it into
+/// characters.
+#[derive(Debug)]
+pub struct Split();
+
+impl Display for Split {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn split>")
+    }
+}
+
+impl LoxCallable for Split {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let s = string_argument(&arguments[0])?;
+        let separator = string_argument(&arguments[1])?;
+        let parts = if separator.is_empty() {
+            s.chars().map(|c| LoxType::String(c.to_string())).collect()
+        } else {
+            s.split(separator)
+                .map(|part| LoxType::String(part.to_owned()))
+                .collect()
+        };
+        Ok(LoxType::list(parts))
+    }
+}
+
+/// Small splitmix64 generator shared by the random natives, so that `seed` makes the
+/// sequences of both `random` and `randomInt` reproducible.
+#[derive(Debug)]
+pub struct Rng {
+    state: Cell<u64>,
+}
+
+impl Rng {
+    pub fn new() -> Rc<Self> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Rc::new(Self {
+            state: Cell::new(now.as_nanos() as u64),
+        })
+    }
+
+    fn next(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9E3779B97F4A7C15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed in [0, 1).
+    fn next_float(&self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[derive(Debug)]
+pub struct Random(pub Rc<Rng>);
+
+impl Display for Random {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn random>")
+    }
+}
+
+impl LoxCallable for Random {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(&self, _arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        Ok(LoxType::Number(self.0.next_float()))
+    }
+}
+
+/// Largest integer up to which every integer is exactly representable as an `f64`.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Random integer between both bounds, inclusive.
+#[derive(Debug)]
+pub struct RandomInt(pub Rc<Rng>);
+
+impl Display for RandomInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn randomInt>")
+    }
+}
+
+impl LoxCallable for RandomInt {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let lo = number_argument(&arguments[0])?;
+        let hi = number_argument(&arguments[1])?;
+        if lo.fract() != 0.0 || hi.fract() != 0.0 {
+            return Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Bounds must be integers.",
+            )));
+        }
+        if lo.abs() > MAX_SAFE_INTEGER || hi.abs() > MAX_SAFE_INTEGER {
+            return Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Bounds must be between -2^53 and 2^53.",
+            )));
+        }
+        let (lo, hi) = (lo as i64, hi as i64);
+        if lo > hi {
+            return Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Lower bound must not be greater than upper bound.",
+            )));
+        }
+        let range = hi
+            .checked_sub(lo)
+            .and_then(|range| range.checked_add(1))
+            .ok_or_else(|| Error::RuntimeError(ErrorDetail::new(0, "Range is too large.")))?;
+        let offset = self.0.next() % range as u64;
+        Ok(LoxType::Number((lo + offset as i64) as f64))
+    }
+}
+
+#[derive(Debug)]
+pub struct Seed(pub Rc<Rng>);
+
+impl Display for Seed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn seed>")
+    }
+}
+
+impl LoxCallable for Seed {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let seed = number_argument(&arguments[0])?;
+        self.0.state.set(seed.to_bits());
+        Ok(LoxType::Nil)
+    }
+}
+
+/// Raises a runtime error with the given message, or a default one, if the condition is falsy.
+#[derive(Debug)]
+pub struct Assert();
+
+impl Display for Assert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn assert>")
+    }
+}
+
+impl LoxCallable for Assert {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        2
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        if arguments[0].is_truthy() {
+            return Ok(LoxType::Nil);
+        }
+        let message = arguments
+            .get(1)
+            .map_or("Assertion failed.".to_owned(), |m| m.to_string());
+        Err(Error::RuntimeError(ErrorDetail::new(0, message)))
+    }
+}
+
+/// `format(template, ...values)` replaces each `{}` in the template with the next value.
+/// `{{` and `}}` stand for literal braces.
+#[derive(Debug)]
+pub struct Format();
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn format>")
+    }
+}
+
+impl LoxCallable for Format {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        usize::MAX
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let error = |message: String| Err(Error::RuntimeError(ErrorDetail::new(0, message)));
+        let template = string_argument(&arguments[0])?;
+        let values = &arguments[1..];
+
+        let mut formatted = String::new();
+        let mut placeholders = 0;
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    formatted.push(c);
+                }
+                ('{', Some('}')) => {
+                    chars.next();
+                    if let Some(value) = values.get(placeholders) {
+                        formatted.push_str(&value.to_string());
+                    }
+                    placeholders += 1;
+                }
+                ('{' | '}', _) => {
+                    return error(format!(
+                        "Unmatched '{c}' in format string, use '{c}{c}' for a literal brace."
+                    ))
+                }
+                _ => formatted.push(c),
+            }
+        }
+        if placeholders != values.len() {
+            return error(format!(
+                "Format string has {placeholders} placeholders but got {} values.",
+                values.len()
+            ));
+        }
+        Ok(LoxType::String(formatted))
+    }
+}
+
+/// `assertEq(actual, expected)` raises a runtime error showing both values if they aren't
+/// equal.
+#[derive(Debug)]
+pub struct AssertEq();
+
+impl Display for AssertEq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn assertEq>")
+    }
+}
+
+impl LoxCallable for AssertEq {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let (actual, expected) = (&arguments[0], &arguments[1]);
+        if actual == expected {
+            return Ok(LoxType::Nil);
+        }
+        // strings are quoted, so "1" and 1 can be told apart
+        let show = |value: &LoxType| match value {
+            LoxType::String(s) => format!("\"{s}\""),
+            value => value.to_string(),
+        };
+        Err(Error::RuntimeError(ErrorDetail::new(
+            0,
+            format!(
+                "assertEq failed: expected {} but got {}.",
+                show(expected),
+                show(actual)
+            ),
+        )))
+    }
+}
+
+/// Ends the program with the given exit code, 0 if there is none.
+#[derive(Debug)]
+pub struct Exit();
+
+impl Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn exit>")
+    }
+}
+
+impl LoxCallable for Exit {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn max_arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let code = arguments.first().map_or(Ok(0.0), number_argument)?;
+        if code.fract() != 0.0 || code < i32::MIN as f64 || code > i32::MAX as f64 {
+            return Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Exit code must be an integer.",
+            )));
+        }
+        Err(Error::Exit(code as i32))
+    }
+}
+
+/// Contents of the file at the given path, nil if it can't be read.
+#[derive(Debug)]
+pub struct ReadFile();
+
+impl Display for ReadFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn readFile>")
+    }
+}
+
+impl LoxCallable for ReadFile {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, _arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        unreachable!("readFile needs a context")
+    }
+
+    fn call_with_ctx(&self, ctx: Context, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let path = string_argument(&arguments[0])?;
+        Ok(ctx.read_file(path).map_or(LoxType::Nil, LoxType::String))
+    }
+}
+
+/// Replaces the contents of the file at the given path, returning whether that succeeded.
+#[derive(Debug)]
+pub struct WriteFile();
+
+impl Display for WriteFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn writeFile>")
+    }
+}
+
+impl LoxCallable for WriteFile {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(&self, _arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        unreachable!("writeFile needs a context")
+    }
+
+    fn call_with_ctx(&self, ctx: Context, arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        let path = string_argument(&arguments[0])?;
+        let contents = string_argument(&arguments[1])?;
+        Ok(LoxType::Boolean(ctx.write_file(path, contents)))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ListMethodKind {
+    Push,
+    Pop,
+    Map,
+    Filter,
+}
+
+/// A method of a list, like `list.push`, bound to the list it was looked up on.
+#[derive(Debug)]
+pub struct ListMethod {
+    list: Rc<RefCell<Vec<LoxType>>>,
+    kind: ListMethodKind,
+}
+
+impl ListMethod {
+    /// The method `name` of `list`, `None` if lists don't have one.
+    pub fn get(list: &Rc<RefCell<Vec<LoxType>>>, name: &str) -> Option<LoxType> {
+        let kind = match name {
+            "push" => ListMethodKind::Push,
+            "pop" => ListMethodKind::Pop,
+            "map" => ListMethodKind::Map,
+            "filter" => ListMethodKind::Filter,
+            _ => return None,
+        };
+        Some(LoxType::Callable(Rc::new(ListMethod {
+            list: list.clone(),
+            kind,
+        })))
+    }
+
+    /// Calls `callback` with each element, working on a copy so it may change the list.
+    fn each(&self, ctx: &Context, callback: &LoxType) -> crate::Result<Vec<(LoxType, LoxType)>> {
+        let elements = self.list.borrow().clone();
+        elements
+            .into_iter()
+            .map(|element| {
+                let result = call_value(ctx.clone(), callback.clone(), vec![element.clone()], 0)?;
+                Ok((element, result))
+            })
+            .collect()
+    }
+}
+
+impl Display for ListMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self.kind {
+            ListMethodKind::Push => "push",
+            ListMethodKind::Pop => "pop",
+            ListMethodKind::Map => "map",
+            ListMethodKind::Filter => "filter",
+        };
+        write!(f, "<native fn {name}>")
+    }
+}
+
+impl LoxCallable for ListMethod {
+    fn arity(&self) -> usize {
+        match self.kind {
+            ListMethodKind::Pop => 0,
+            ListMethodKind::Push | ListMethodKind::Map | ListMethodKind::Filter => 1,
+        }
+    }
+
+    fn call(&self, _arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        unreachable!("list methods need a context")
+    }
+
+    fn call_with_ctx(&self, ctx: Context, mut arguments: Vec<LoxType>) -> crate::Result<LoxType> {
+        match self.kind {
+            ListMethodKind::Push => {
+                self.list.borrow_mut().push(arguments.remove(0));
+                Ok(LoxType::Nil)
+            }
+            ListMethodKind::Pop => self.list.borrow_mut().pop().ok_or_else(|| {
+                Error::RuntimeError(ErrorDetail::new(0, "Can't pop from an empty list."))
+            }),
+            ListMethodKind::Map => {
+                let results = self.each(&ctx, &arguments[0])?;
+                Ok(LoxType::list(
+                    results.into_iter().map(|(_, result)| result).collect(),
+                ))
+            }
+            ListMethodKind::Filter => {
+                let results = self.each(&ctx, &arguments[0])?;
+                Ok(LoxType::list(
+                    results
+                        .into_iter()
+                        .filter(|(_, keep)| keep.is_truthy())
+                        .map(|(element, _)| element)
+                        .collect(),
+                ))
+            }
+        }
+    }
+
+    fn trace(&self, marker: &mut Marker) {
+        marker.mark_list(&self.list);
     }
 }
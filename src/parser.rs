@@ -1,9 +1,10 @@
-use std::{collections::HashMap, iter::Peekable, rc::Rc};
+use std::{borrow::Cow, iter::Peekable, rc::Rc};
 
 use crate::{
     ast::*,
     error::{Error, ErrorDetail},
     loxtype::LoxType,
+    symbol::Symbol,
     token::{
         Literal, Token,
         TokenType::{self, *},
@@ -21,6 +22,10 @@ pub struct Parser<'a> {
     tokens: Peekable<std::iter::Take<std::slice::Iter<'a, Token>>>,
     errors: Vec<ErrorDetail>,
     last_line: u32,
+    /// Set when an error happened because the tokens ran out, so more input could fix it.
+    reached_end: bool,
+    /// Desugar `a < b < c` to `a < b and b < c` instead of reporting it.
+    allow_chained_comparisons: bool,
 }
 
 impl<'a> Parser<'a> {
@@ -33,9 +38,17 @@ impl<'a> Parser<'a> {
                 .get(tokens.len().wrapping_sub(2))
                 .map(|t| t.line)
                 .unwrap_or(1),
+            reached_end: false,
+            allow_chained_comparisons: false,
         }
     }
 
+    /// Opts in to chained comparisons like `0 <= x < 10`, which are an error otherwise.
+    pub fn with_chained_comparisons(mut self, allow: bool) -> Self {
+        self.allow_chained_comparisons = allow;
+        self
+    }
+
     pub fn parse(mut self) -> Result<Vec<Box<dyn Statement>>> {
         let mut statements = vec![];
 
@@ -51,6 +64,9 @@ impl<'a> Parser<'a> {
 
         if self.errors.is_empty() {
             Ok(statements)
+        } else if self.reached_end && self.errors.len() == 1 {
+            // the input is fine so far, it just stops in the middle of a statement
+            Err(Error::IncompleteInput(self.errors.pop().unwrap()))
         } else {
             Err(Error::SyntaxErrors(self.errors))
         }
@@ -63,10 +79,21 @@ impl<'a> Parser<'a> {
                 self.tokens.next();
                 return;
             }
-            if [Class, Fun, Var, For, If, While, Print, Return]
-                .iter()
-                .any(|&tt| tt == ty)
-            {
+            if matches!(
+                ty,
+                Class
+                    | Fun
+                    | Var
+                    | Const
+                    | For
+                    | If
+                    | While
+                    | Do
+                    | Print
+                    | Return
+                    | Break
+                    | Continue
+            ) {
                 return;
             };
             self.tokens.next();
@@ -93,30 +120,56 @@ impl<'a> Parser<'a> {
         self.tokens.next_if(|t| t.ty == tt).is_some()
     }
 
+    /// Checks the type of the next token without consuming it.
+    fn is_at(&mut self, tt: TokenType) -> bool {
+        self.tokens.peek().is_some_and(|t| t.ty == tt)
+    }
+
     fn consume(&mut self, token_ty: TokenType) -> std::result::Result<&'a Token, ErrorDetail> {
         if let Some(n) = self.tokens.peek() {
             if n.ty == token_ty {
                 Ok(self.tokens.next().unwrap())
             } else {
-                Err(ErrorDetail::new(n.line, format!("Expect '{token_ty}'.")))
+                Err(ErrorDetail::new_with_col(
+                    n.line,
+                    n.column,
+                    format!("Expect '{token_ty}', found '{}'.", n.source_text()),
+                ))
             }
         } else {
-            Err(ErrorDetail::new(
-                self.last_line,
-                format!("Expect '{token_ty}'."),
-            ))
+            Err(self.end_of_input(format!("Expect '{token_ty}'.")))
         }
     }
 
+    /// The error for a token that is missing because the input ended.
+    fn end_of_input(&mut self, message: impl Into<Cow<'static, str>>) -> ErrorDetail {
+        self.reached_end = true;
+        ErrorDetail::new(self.last_line, message)
+    }
+
     fn declaration(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
-        match self.tokens.peek().unwrap().ty {
+        let ty = self.tokens.peek().unwrap().ty;
+        match ty {
             Class => self.class_declaration(),
-            Var => self.var_declaration(),
-            Fun => Ok(Box::new(self.function(FunctionKind::Function)?)),
+            Var | Const => self.var_declaration(),
+            // `fun (` starts an anonymous function expression
+            Fun if !self.is_lambda_ahead() => Ok(Box::new(self.function(FunctionKind::Function)?)),
             _ => self.statement(),
         }
     }
 
+    fn is_lambda_ahead(&self) -> bool {
+        let mut lookahead = self.tokens.clone();
+        lookahead.next();
+        lookahead.peek().is_some_and(|t| t.ty == LeftParen)
+    }
+
+    fn is_keyword_argument_ahead(&self) -> bool {
+        let mut lookahead = self.tokens.clone();
+        lookahead.next().is_some_and(|t| t.ty == Identifier)
+            && lookahead.next().is_some_and(|t| t.ty == Colon)
+    }
+
     fn class_declaration(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
         let class_token = self.tokens.next().unwrap();
         let name = self.consume(Identifier)?;
@@ -127,8 +180,8 @@ impl<'a> Parser<'a> {
             .map(|_| {
                 let identifier_token = self.consume(Identifier)?;
                 Ok(VariableExpression {
-                    name: identifier_token.lexeme.clone(),
-                    maybe_distance: None,
+                    name: identifier_token.symbol(),
+                    maybe_slot: None,
                     line: identifier_token.line,
                 })
             })
@@ -136,17 +189,22 @@ impl<'a> Parser<'a> {
 
         self.consume(LeftBrace)?;
 
-        let mut methods: HashMap<std::string::String, FunctionStatement> = HashMap::new();
+        let mut methods = vec![];
+        let mut static_methods = vec![];
         while self.tokens.peek().is_some_and(|t| t.ty != RightBrace) {
-            let m = self.function(FunctionKind::Method)?;
-            methods.insert(m.name.clone(), m);
+            if self.is_next_token_type(Class) {
+                static_methods.push(self.function(FunctionKind::Method)?);
+            } else {
+                methods.push(self.function(FunctionKind::Method)?);
+            }
         }
 
         self.consume(RightBrace)?;
 
         Ok(Box::new(ClassStatement {
-            name: name.lexeme.clone(),
+            name: name.symbol(),
             methods: Rc::new(methods),
+            static_methods: Rc::new(static_methods),
             maybe_superclass,
             line: class_token.line,
         }))
@@ -162,37 +220,26 @@ impl<'a> Parser<'a> {
             self.tokens.peek().map_or(self.last_line, |t| t.line)
         };
         if let Some(name_token) = self.tokens.next_if(|t| t.ty == Identifier) {
-            let name = name_token.lexeme.clone();
-
-            self.consume(LeftParen)?;
-            let mut parameters = vec![];
-            if self.tokens.peek().is_some_and(|t| t.ty != RightParen) {
-                loop {
-                    let identifier = self.consume(Identifier)?;
-                    parameters.push(Parameter {
-                        name: identifier.lexeme.clone(),
-                        line: identifier.line,
-                    });
-                    if !self.is_next_token_type(Comma) {
-                        break;
-                    }
-                }
-            }
-            let paren_token = self.consume(RightParen)?;
-            if parameters.len() > 255 {
-                self.errors.push(ErrorDetail::new(
-                    paren_token.line,
-                    "Can't have more than 255 parameters.",
-                ));
+            let name = name_token.symbol();
+
+            // a method without a parameter list is a getter
+            if kind == FunctionKind::Method && self.is_next_token_type(LeftBrace) {
+                let block = self.block_statement()?;
+                return Ok(FunctionStatement {
+                    name,
+                    parameters: vec![],
+                    statements: Rc::new(block.statements),
+                    is_getter: true,
+                    line: function_line,
+                });
             }
 
-            self.consume(LeftBrace)?;
-            let block: BlockStatement = self.block_statement()?;
-
+            let (parameters, block) = self.parameters_and_body()?;
             Ok(FunctionStatement {
                 name,
                 parameters,
                 statements: Rc::new(block.statements),
+                is_getter: false,
                 line: function_line,
             })
         } else {
@@ -200,15 +247,65 @@ impl<'a> Parser<'a> {
                 FunctionKind::Function => "Expect function name.",
                 FunctionKind::Method => "Expect method name.",
             };
-            Err(ErrorDetail::new(function_line, message))
+            if self.tokens.peek().is_none() {
+                Err(self.end_of_input(message))
+            } else {
+                Err(ErrorDetail::new(function_line, message))
+            }
         }
     }
 
+    fn parameters_and_body(
+        &mut self,
+    ) -> std::result::Result<(Vec<Parameter>, BlockStatement), ErrorDetail> {
+        self.consume(LeftParen)?;
+        let mut parameters = vec![];
+        if self.tokens.peek().is_some_and(|t| t.ty != RightParen) {
+            loop {
+                let is_rest = self.is_next_token_type(DotDotDot);
+                let identifier = self.consume(Identifier)?;
+                parameters.push(Parameter {
+                    name: identifier.symbol(),
+                    is_rest,
+                    line: identifier.line,
+                });
+                // a trailing comma is allowed before the closing paren
+                if !self.is_next_token_type(Comma) || self.is_at(RightParen) {
+                    break;
+                }
+                if is_rest {
+                    return Err(ErrorDetail::new_with_col(
+                        identifier.line,
+                        identifier.column,
+                        "Rest parameter must be the last parameter.",
+                    ));
+                }
+            }
+        }
+        let paren_token = self.consume(RightParen)?;
+        if parameters.len() > 255 {
+            self.errors.push(ErrorDetail::new_with_col(
+                paren_token.line,
+                paren_token.column,
+                "Can't have more than 255 parameters.",
+            ));
+        }
+
+        self.consume(LeftBrace)?;
+        let block = self.block_statement()?;
+        Ok((parameters, block))
+    }
+
     fn var_declaration(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
         let var_token = self.tokens.next().unwrap();
         let name = self.consume(Identifier)?;
+        let is_const = var_token.ty == Const;
 
-        let initializer = if self.is_next_token_type(Equal) {
+        let initializer = if is_const {
+            // a constant without a value would be nil forever
+            self.consume(Equal)?;
+            Some(self.expression()?)
+        } else if self.is_next_token_type(Equal) {
             Some(self.expression()?)
         } else {
             None
@@ -216,14 +313,21 @@ impl<'a> Parser<'a> {
         self.consume(Semicolon)?;
 
         Ok(Box::new(VarStatement {
-            name: name.lexeme.clone(),
+            name: name.symbol(),
             initializer: initializer,
+            is_const,
             line: var_token.line,
         }))
     }
 
     fn statement(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
         match self.tokens.peek().unwrap().ty {
+            Break => self.break_statement(),
+            Continue => self.continue_statement(),
+            Do => {
+                self.tokens.next();
+                self.do_while_statement()
+            }
             For => {
                 self.tokens.next();
                 self.for_statement()
@@ -256,12 +360,36 @@ impl<'a> Parser<'a> {
         self.consume(Semicolon)?;
         Ok(Box::new(ReturnStatement {
             maybe_expression,
+            is_tail_call: false,
             line: return_token.line,
         }))
     }
 
+    fn break_statement(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
+        let break_token = self.tokens.next().unwrap();
+        self.consume(Semicolon)?;
+        Ok(Box::new(BreakStatement {
+            line: break_token.line,
+        }))
+    }
+
+    fn continue_statement(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
+        let continue_token = self.tokens.next().unwrap();
+        self.consume(Semicolon)?;
+        Ok(Box::new(ContinueStatement {
+            line: continue_token.line,
+        }))
+    }
+
     fn for_statement(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
-        self.consume(LeftParen)?;
+        let line = self.consume(LeftParen)?.line;
+
+        let mut lookahead = self.tokens.clone();
+        if lookahead.next().is_some_and(|t| t.ty == Identifier)
+            && lookahead.next().is_some_and(|t| t.ty == In)
+        {
+            return self.for_each_statement();
+        }
 
         let opt_initializer = if self.is_next_token_type(Semicolon) {
             None
@@ -294,21 +422,17 @@ impl<'a> Parser<'a> {
         // initializer;
         // while(condition) {
         //  body;
-        //  increment;
-        // }
+        // } with increment run after each iteration
         //}
         let condition =
             opt_for_condition.unwrap_or(Box::new(LiteralExpression(LoxType::Boolean(true))));
 
-        let mut body_statements: Vec<Box<dyn Statement>> = vec![for_body];
-        if let Some(increment) = opt_increment {
-            body_statements.push(Box::new(ExpressionStatement(increment)));
-        }
-        let body = Box::new(BlockStatement {
-            statements: body_statements,
+        let while_statement = Box::new(WhileStatement {
+            condition,
+            body: for_body,
+            maybe_increment: opt_increment,
+            line,
         });
-
-        let while_statement = Box::new(WhileStatement { condition, body });
         let mut block_statements: Vec<Box<dyn Statement>> = vec![];
         if let Some(initializer) = opt_initializer {
             block_statements.push(initializer);
@@ -320,12 +444,45 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    fn for_each_statement(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
+        let name_token = self.consume(Identifier)?;
+        self.consume(In)?;
+        let iterable = self.expression()?;
+        self.consume(RightParen)?;
+        let body = self.statement()?;
+        Ok(Box::new(ForEachStatement {
+            name: name_token.symbol(),
+            iterable,
+            body,
+            line: name_token.line,
+        }))
+    }
+
     fn while_statement(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
-        self.consume(LeftParen)?;
+        let line = self.consume(LeftParen)?.line;
         let condition = self.expression()?;
         self.consume(RightParen)?;
         let body = self.statement()?;
-        Ok(Box::new(WhileStatement { condition, body }))
+        Ok(Box::new(WhileStatement {
+            condition,
+            body,
+            maybe_increment: None,
+            line,
+        }))
+    }
+
+    fn do_while_statement(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
+        let body = self.statement()?;
+        let line = self.consume(While)?.line;
+        self.consume(LeftParen)?;
+        let condition = self.expression()?;
+        self.consume(RightParen)?;
+        self.consume(Semicolon)?;
+        Ok(Box::new(DoWhileStatement {
+            body,
+            condition,
+            line,
+        }))
     }
 
     fn if_statement(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
@@ -347,10 +504,13 @@ impl<'a> Parser<'a> {
 
     fn print_statement(&mut self) -> std::result::Result<Box<dyn Statement>, ErrorDetail> {
         let print_token = self.tokens.next().unwrap();
-        let expression = self.expression()?;
+        let mut expressions = vec![self.expression()?];
+        while self.is_next_token_type(Comma) {
+            expressions.push(self.expression()?);
+        }
         self.consume(Semicolon)?;
         Ok(Box::new(PrintStatement {
-            expression,
+            expressions,
             line: print_token.line,
         }))
     }
@@ -381,30 +541,73 @@ impl<'a> Parser<'a> {
     }
 
     fn assignment(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
-        let expr = self.or()?;
+        let expr = self.ternary()?;
 
-        if let Some(eq_token) = self.match_token_type(Equal) {
-            let value = self.assignment()?;
+        if let Some(eq_token) =
+            self.match_token_types(&[Equal, PlusEqual, MinusEqual, StarEqual, SlashEqual])
+        {
+            let maybe_operator = match eq_token.ty {
+                Equal => None,
+                PlusEqual => Some(BinaryOperator::Add),
+                MinusEqual => Some(BinaryOperator::Substract),
+                StarEqual => Some(BinaryOperator::Multiply),
+                SlashEqual => Some(BinaryOperator::Divide),
+                _ => unreachable!(),
+            };
+            let mut value = self.assignment()?;
 
             let expr_any = expr.as_any();
             if let Some(var_expr) = expr_any.downcast_ref::<VariableExpression>() {
+                // desugar `a += b` as `a = a + b`
+                if let Some(operator) = maybe_operator {
+                    value = Box::new(BinaryExpression {
+                        left: Box::new(VariableExpression {
+                            name: var_expr.name.clone(),
+                            maybe_slot: None,
+                            line: var_expr.line,
+                        }),
+                        right: value,
+                        operator,
+                        line: eq_token.line,
+                    });
+                }
                 return Ok(Box::new(AssignExpression {
                     name: var_expr.name.clone(),
                     value: value,
-                    maybe_distance: None,
+                    maybe_slot: None,
                     line: eq_token.line,
                 }));
-            } else if expr_any.is::<GetExpression>() {
+            } else if expr_any
+                .downcast_ref::<GetExpression>()
+                .is_some_and(|get_expr| !get_expr.is_optional)
+            {
                 let get_expr = expr.into_any().downcast::<GetExpression>().unwrap();
                 return Ok(Box::new(SetExpression {
                     object: get_expr.object,
                     name: get_expr.name,
                     value: value,
+                    maybe_operator,
+                    line: eq_token.line,
+                }));
+            } else if expr_any.is::<IndexExpression>() {
+                let index_expr = expr.into_any().downcast::<IndexExpression>().unwrap();
+                return Ok(Box::new(IndexSetExpression {
+                    object: index_expr.object,
+                    index: index_expr.index,
+                    value,
+                    maybe_operator,
                     line: eq_token.line,
                 }));
+            } else if expr_any.is::<ThisExpression>() {
+                self.errors.push(ErrorDetail::new_with_col(
+                    eq_token.line,
+                    eq_token.column,
+                    "Cannot assign to 'this'.",
+                ));
             } else {
-                self.errors.push(ErrorDetail::new(
+                self.errors.push(ErrorDetail::new_with_col(
                     eq_token.line,
+                    eq_token.column,
                     "Invalid assignment target.",
                 ));
             }
@@ -412,6 +615,36 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
+    fn ternary(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
+        let expr = self.coalesce()?;
+
+        if self.is_next_token_type(Question) {
+            let then_branch = self.expression()?;
+            self.consume(Colon)?;
+            let else_branch = self.ternary()?;
+            return Ok(Box::new(TernaryExpression {
+                condition: expr,
+                then_branch,
+                else_branch,
+            }));
+        }
+        Ok(expr)
+    }
+
+    fn coalesce(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
+        let mut expr = self.or()?;
+
+        while self.is_next_token_type(QuestionQuestion) {
+            let right = self.or()?;
+            expr = Box::new(LogicalExpression {
+                left: expr,
+                right,
+                operator: LogicalOperator::Coalesce,
+            });
+        }
+        Ok(expr)
+    }
+
     fn or(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
         let mut expr = self.and()?;
 
@@ -465,38 +698,108 @@ impl<'a> Parser<'a> {
     }
 
     fn comparison(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
-        let mut expr = self.term()?;
+        let first = self.bit_or()?;
+        let mut links = vec![];
 
         while let Some(operator) = self.match_token_types(&[Greater, GreaterEqual, Less, LessEqual])
         {
+            // `1 < 2 < 3` would compare a boolean with a number, so it's reported right away
+            // unless chains are desugared. Then `0 <= x < 10` is a range check, but
+            // `a < b > c` doesn't read as anything
+            let is_ascending = |ty| matches!(ty, Less | LessEqual);
+            if let Some((first_ty, _, _)) = links.first() {
+                if !self.allow_chained_comparisons {
+                    self.errors.push(ErrorDetail::new_with_col(
+                        operator.line,
+                        operator.column,
+                        "Chained comparisons are not allowed; use 'and'.",
+                    ));
+                } else if is_ascending(*first_ty) != is_ascending(operator.ty) {
+                    self.errors.push(ErrorDetail::new_with_col(
+                        operator.line,
+                        operator.column,
+                        "Chained comparisons must all go the same way; use 'and'.",
+                    ));
+                }
+            }
+            let right = self.bit_or()?;
+            links.push((operator.ty, operator.line, right));
+        }
+
+        if links.len() <= 1 {
+            return Ok(match links.pop() {
+                Some((ty, line, right)) => comparison_expression(first, ty, line, right),
+                None => first,
+            });
+        }
+        Ok(chain_comparisons(first, links))
+    }
+
+    /// The bitwise operators bind tighter than comparisons, unlike in C, so `x & 1 == 0`
+    /// means `(x & 1) == 0`.
+    fn bit_or(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
+        let mut expr = self.bit_xor()?;
+
+        while let Some(operator) = self.match_token_type(Pipe) {
+            let right = self.bit_xor()?;
+            expr = Box::new(BinaryExpression {
+                left: expr,
+                right,
+                operator: BinaryOperator::BitOr,
+                line: operator.line,
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_xor(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
+        let mut expr = self.bit_and()?;
+
+        while let Some(operator) = self.match_token_type(Caret) {
+            let right = self.bit_and()?;
+            expr = Box::new(BinaryExpression {
+                left: expr,
+                right,
+                operator: BinaryOperator::BitXor,
+                line: operator.line,
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_and(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
+        let mut expr = self.shift()?;
+
+        while let Some(operator) = self.match_token_type(Amp) {
+            let right = self.shift()?;
+            expr = Box::new(BinaryExpression {
+                left: expr,
+                right,
+                operator: BinaryOperator::BitAnd,
+                line: operator.line,
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
+        let mut expr = self.term()?;
+
+        while let Some(operator) = self.match_token_types(&[LessLess, GreaterGreater]) {
             let right = self.term()?;
-            expr = match operator.ty {
-                Greater => Box::new(BinaryExpression {
-                    left: expr,
-                    right: right,
-                    operator: BinaryOperator::Greater,
-                    line: operator.line,
-                }),
-                GreaterEqual => Box::new(BinaryExpression {
-                    left: expr,
-                    right: right,
-                    operator: BinaryOperator::GreaterOrEqual,
-                    line: operator.line,
-                }),
-                Less => Box::new(BinaryExpression {
-                    left: expr,
-                    right: right,
-                    operator: BinaryOperator::Less,
-                    line: operator.line,
-                }),
-                LessEqual => Box::new(BinaryExpression {
-                    left: expr,
-                    right: right,
-                    operator: BinaryOperator::LessOrEqual,
-                    line: operator.line,
-                }),
-                _ => unreachable!(),
-            };
+            expr = Box::new(BinaryExpression {
+                left: expr,
+                right,
+                operator: match operator.ty {
+                    LessLess => BinaryOperator::ShiftLeft,
+                    GreaterGreater => BinaryOperator::ShiftRight,
+                    _ => unreachable!(),
+                },
+                line: operator.line,
+            });
         }
 
         Ok(expr)
@@ -505,9 +808,15 @@ impl<'a> Parser<'a> {
     fn term(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
         let mut expr = self.factor()?;
 
-        while let Some(operator) = self.match_token_types(&[Minus, Plus]) {
+        while let Some(operator) = self.match_token_types(&[Minus, Plus, DotDot]) {
             let right = self.factor()?;
             expr = match operator.ty {
+                DotDot => Box::new(BinaryExpression {
+                    left: expr,
+                    right,
+                    operator: BinaryOperator::Concat,
+                    line: operator.line,
+                }),
                 Minus => Box::new(BinaryExpression {
                     left: expr,
                     right: right,
@@ -530,7 +839,7 @@ impl<'a> Parser<'a> {
     fn factor(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
         let mut expr = self.unary()?;
 
-        while let Some(operator) = self.match_token_types(&[Star, Slash]) {
+        while let Some(operator) = self.match_token_types(&[Star, Slash, Percent]) {
             let right = self.unary()?;
             expr = match operator.ty {
                 Star => Box::new(BinaryExpression {
@@ -545,6 +854,12 @@ impl<'a> Parser<'a> {
                     operator: BinaryOperator::Divide,
                     line: operator.line,
                 }),
+                Percent => Box::new(BinaryExpression {
+                    left: expr,
+                    right: right,
+                    operator: BinaryOperator::Modulo,
+                    line: operator.line,
+                }),
                 _ => unreachable!(),
             };
         }
@@ -553,11 +868,15 @@ impl<'a> Parser<'a> {
     }
 
     fn unary(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
-        if let Some(operator) = self.match_token_types(&[Bang, Minus]) {
+        if let Some(operator) = self.match_token_types(&[Bang, Minus, Tilde]) {
             let expression = self.unary()?;
 
             return Ok(match operator.ty {
                 Bang => Box::new(NotExpression(expression)),
+                Tilde => Box::new(BitNotExpression {
+                    expression,
+                    line: operator.line,
+                }),
                 Minus => Box::new(NegExpression {
                     expression,
                     line: operator.line,
@@ -566,7 +885,25 @@ impl<'a> Parser<'a> {
             });
         }
 
-        self.call()
+        self.power()
+    }
+
+    /// Binds tighter than unary operators on its left, so `-2 ** 2` is `-(2 ** 2)`, and is
+    /// right-associative.
+    fn power(&mut self) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
+        let expr = self.call()?;
+
+        if let Some(operator) = self.match_token_type(StarStar) {
+            let right = self.unary()?;
+            return Ok(Box::new(BinaryExpression {
+                left: expr,
+                right,
+                operator: BinaryOperator::Power,
+                line: operator.line,
+            }));
+        }
+
+        Ok(expr)
     }
 
     fn finish_call(
@@ -574,19 +911,35 @@ impl<'a> Parser<'a> {
         callee: Box<dyn Expression>,
     ) -> std::result::Result<Box<dyn Expression>, ErrorDetail> {
         let mut arguments = vec![];
+        let mut keyword_arguments = vec![];
 
         if self.tokens.peek().is_some_and(|t| t.ty != RightParen) {
             loop {
-                arguments.push(self.expression()?);
-                if !self.is_next_token_type(Comma) {
+                if self.is_keyword_argument_ahead() {
+                    let name = self.tokens.next().unwrap();
+                    self.tokens.next();
+                    keyword_arguments.push((name.symbol(), self.expression()?));
+                } else if let (Some(token), false) =
+                    (self.tokens.peek(), keyword_arguments.is_empty())
+                {
+                    return Err(ErrorDetail::new_with_col(
+                        token.line,
+                        token.column,
+                        "Positional arguments must come before keyword arguments.",
+                    ));
+                } else {
+                    arguments.push(self.expression()?);
+                }
+                if !self.is_next_token_type(Comma) || self.is_at(RightParen) {
                     break;
                 }
             }
         }
         let paren_token = self.consume(RightParen)?;
-        if arguments.len() > 255 {
-            self.errors.push(ErrorDetail::new(
+        if arguments.len() + keyword_arguments.len() > 255 {
+            self.errors.push(ErrorDetail::new_with_col(
                 paren_token.line,
+                paren_token.column,
                 "Can't have more than 255 arguments.",
             ));
         }
@@ -594,6 +947,7 @@ impl<'a> Parser<'a> {
         Ok(Box::new(CallExpression {
             callee,
             arguments,
+            keyword_arguments,
             line: paren_token.line,
         }))
     }
@@ -604,13 +958,22 @@ impl<'a> Parser<'a> {
         loop {
             if self.is_next_token_type(LeftParen) {
                 expr = self.finish_call(expr)?;
-            } else if self.is_next_token_type(Dot) {
+            } else if let Some(dot) = self.match_token_types(&[Dot, QuestionDot]) {
                 let name = self.consume(Identifier)?;
                 expr = Box::new(GetExpression {
-                    name: name.lexeme.clone(),
+                    name: name.symbol(),
                     object: expr,
+                    is_optional: dot.ty == QuestionDot,
                     line: name.line,
                 });
+            } else if let Some(bracket_token) = self.match_token_type(LeftBracket) {
+                let index = self.expression()?;
+                self.consume(RightBracket)?;
+                expr = Box::new(IndexExpression {
+                    object: expr,
+                    index,
+                    line: bracket_token.line,
+                });
             } else {
                 break;
             }
@@ -645,31 +1008,133 @@ impl<'a> Parser<'a> {
                     Ok(Box::new(GroupingExpression(expr)))
                 }
                 Identifier => Ok(Box::new(VariableExpression {
-                    name: token.lexeme.clone(),
-                    maybe_distance: None,
+                    name: token.symbol(),
+                    maybe_slot: None,
                     line: token.line,
                 })),
+                LeftBracket => {
+                    let mut elements = vec![];
+                    if self.tokens.peek().is_some_and(|t| t.ty != RightBracket) {
+                        loop {
+                            elements.push(self.expression()?);
+                            if !self.is_next_token_type(Comma) {
+                                break;
+                            }
+                        }
+                    }
+                    self.consume(RightBracket)?;
+                    Ok(Box::new(ListExpression { elements }))
+                }
+                LeftBrace => {
+                    let mut entries = vec![];
+                    if self.tokens.peek().is_some_and(|t| t.ty != RightBrace) {
+                        loop {
+                            let key = self.expression()?;
+                            self.consume(Colon)?;
+                            let value = self.expression()?;
+                            entries.push((key, value));
+                            if !self.is_next_token_type(Comma) {
+                                break;
+                            }
+                        }
+                    }
+                    self.consume(RightBrace)?;
+                    Ok(Box::new(MapExpression {
+                        entries,
+                        line: token.line,
+                    }))
+                }
+                Fun => {
+                    let (parameters, block) = self.parameters_and_body()?;
+                    Ok(Box::new(FunctionExpression {
+                        parameters,
+                        statements: Rc::new(block.statements),
+                    }))
+                }
                 This => Ok(Box::new(ThisExpression {
-                    maybe_distance: None,
+                    maybe_slot: None,
                     line: token.line,
                 })),
                 Super => {
                     self.consume(Dot)?;
                     let method = self.consume(Identifier)?;
                     Ok(Box::new(SuperExpression {
-                        method: method.lexeme.clone(),
+                        method: method.symbol(),
                         line: token.line,
-                        maybe_distance: None,
+                        maybe_slot: None,
                     }))
                 }
-                _ => Err(ErrorDetail::new(token.line, "Expect expression.")),
+                _ => Err(ErrorDetail::new_with_col(
+                    token.line,
+                    token.column,
+                    format!("Expect expression, found '{}'.", token.source_text()),
+                )),
             }
         } else {
-            Err(ErrorDetail::new(self.last_line, "Expect expression."))
+            Err(self.end_of_input("Expect expression."))
         }
     }
 }
 
+fn comparison_expression(
+    left: Box<dyn Expression>,
+    ty: TokenType,
+    line: u32,
+    right: Box<dyn Expression>,
+) -> Box<dyn Expression> {
+    let operator = match ty {
+        Greater => BinaryOperator::Greater,
+        GreaterEqual => BinaryOperator::GreaterOrEqual,
+        Less => BinaryOperator::Less,
+        LessEqual => BinaryOperator::LessOrEqual,
+        _ => unreachable!(),
+    };
+    Box::new(BinaryExpression {
+        left,
+        right,
+        operator,
+        line,
+    })
+}
+
+/// Desugars `a < b < c` to `a < b and b < c`, with each operand but the last bound to a
+/// temporary so it's evaluated once and in order.
+fn chain_comparisons(
+    first: Box<dyn Expression>,
+    links: Vec<(TokenType, u32, Box<dyn Expression>)>,
+) -> Box<dyn Expression> {
+    let temporary = |index: usize| Symbol::intern(&format!("<operand {index}>"));
+    let read = |index: usize, line: u32| -> Box<dyn Expression> {
+        Box::new(VariableExpression {
+            name: temporary(index),
+            maybe_slot: None,
+            line,
+        })
+    };
+
+    // built from the last comparison outwards
+    let mut links = links.into_iter().enumerate().rev();
+    let (last_index, (ty, line, right)) = links.next().unwrap();
+    let mut body = comparison_expression(read(last_index, line), ty, line, right);
+    for (index, (ty, line, right)) in links {
+        body = Box::new(LogicalExpression {
+            left: comparison_expression(read(index, line), ty, line, read(index + 1, line)),
+            right: body,
+            operator: LogicalOperator::And,
+        });
+        body = Box::new(LetExpression {
+            name: temporary(index + 1),
+            value: right,
+            body,
+        });
+    }
+    Box::new(LetExpression {
+        name: temporary(0),
+        value: first,
+        body,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -689,4 +1154,12 @@ mod tests {
             assert_debug_snapshot!(parser.parse());
         });
     }
+
+    #[test]
+    fn test_chained_comparisons() {
+        let input = "print 1 < 2 <= 3;";
+        let tokens = scan_tokens(input).unwrap();
+        let parser = Parser::new(&tokens).with_chained_comparisons(true);
+        assert_debug_snapshot!(parser.parse());
+    }
 }
@@ -1,10 +1,11 @@
 mod resolve_expr;
 mod resolve_stmt;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::ast::Statement;
+use crate::ast::{Slot, Statement};
 use crate::error::{Error, ErrorDetail};
+use crate::symbol::Symbol;
 use crate::Result;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -20,26 +21,55 @@ enum ClassType {
     Subclass,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum VariableState {
     Declared,
     Defined,
+    /// Defined with `const`.
+    Const,
+}
+
+/// A local variable and the slot it gets in the environment of its scope. Slots are handed
+/// out in the order the variables are defined at runtime.
+#[derive(Debug)]
+struct Local {
+    state: VariableState,
+    index: usize,
 }
 
-pub(crate) struct Scopes {
-    scopes: Vec<HashMap<String, VariableState>>,
+/// Settings for one run of the resolver.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ResolveOptions {
+    /// Report locals that shadow a variable of an enclosing local scope.
+    pub warn_shadowing: bool,
+    /// The statements are a whole script, in which `return;` outside of a function ends the
+    /// program early. Elsewhere, like at the prompt, a top-level return is an error.
+    pub is_script: bool,
+}
+
+pub struct Scopes {
+    scopes: Vec<HashMap<Symbol, Local>>,
     function_types: Vec<FunctionType>,
     class_types: Vec<ClassType>,
+    /// Globals aren't tracked in `scopes`, so constants among them are remembered here.
+    global_consts: HashSet<Symbol>,
+    loop_depth: u32,
     errors: Vec<ErrorDetail>,
+    options: ResolveOptions,
+    warnings: Vec<ErrorDetail>,
 }
 
 impl Scopes {
-    pub fn new() -> Self {
+    pub fn new(options: ResolveOptions) -> Self {
         Self {
             scopes: vec![],
             function_types: vec![],
             class_types: vec![],
+            global_consts: HashSet::new(),
+            loop_depth: 0,
             errors: vec![],
+            options,
+            warnings: vec![],
         }
     }
 
@@ -59,6 +89,14 @@ impl Scopes {
         self.function_types.pop();
     }
 
+    pub fn begin_loop(&mut self) {
+        self.loop_depth += 1;
+    }
+
+    pub fn end_loop(&mut self) {
+        self.loop_depth -= 1;
+    }
+
     pub(self) fn begin_class(&mut self, class_type: ClassType) {
         self.class_types.push(class_type);
     }
@@ -67,7 +105,13 @@ impl Scopes {
         self.class_types.pop();
     }
 
-    pub fn declare(&mut self, name: &str, line: u32) {
+    pub fn declare(&mut self, name: &Symbol, line: u32) {
+        if self.options.warn_shadowing && self.is_shadowing(name) {
+            self.warnings.push(ErrorDetail::new(
+                line,
+                format!("Variable '{name}' shadows an outer binding."),
+            ));
+        }
         if let Some(hm) = self.scopes.last_mut() {
             if hm.contains_key(name) {
                 self.errors.push(ErrorDetail::new(
@@ -75,23 +119,72 @@ impl Scopes {
                     "Already a variable with this name in this scope.",
                 ));
             } else {
-                hm.insert(name.to_owned(), VariableState::Declared);
+                let index = hm.len();
+                hm.insert(
+                    name.clone(),
+                    Local {
+                        state: VariableState::Declared,
+                        index,
+                    },
+                );
             }
         }
     }
 
-    pub fn define(&mut self, name: &str) {
-        if let Some(hm) = self.scopes.last_mut() {
-            hm.insert(name.to_owned(), VariableState::Defined);
+    /// Whether a local scope around the current one has a variable called `name`.
+    fn is_shadowing(&self, name: &Symbol) -> bool {
+        let Some((_, enclosing)) = self.scopes.split_last() else {
+            return false;
+        };
+        enclosing.iter().any(|hm| hm.contains_key(name))
+    }
+
+    fn set_state(&mut self, name: &Symbol, state: VariableState) -> bool {
+        let Some(hm) = self.scopes.last_mut() else {
+            return false;
+        };
+        let index = hm.len();
+        hm.entry(name.clone())
+            .and_modify(|local| local.state = state)
+            .or_insert(Local { state, index });
+        true
+    }
+
+    pub fn define(&mut self, name: &Symbol) {
+        self.set_state(name, VariableState::Defined);
+    }
+
+    pub fn define_const(&mut self, name: &Symbol) {
+        if !self.set_state(name, VariableState::Const) {
+            self.global_consts.insert(name.clone());
         }
     }
 
-    pub fn check_initialized(&mut self, name: &str, line: u32) {
-        if self
-            .scopes
-            .last()
-            .is_some_and(|hm| hm.get(name).is_some_and(|v| *v == VariableState::Declared))
-        {
+    /// Redeclaring a global with `var` makes it assignable again.
+    pub fn define_global(&mut self, name: &Symbol) {
+        if self.scopes.is_empty() {
+            self.global_consts.remove(name);
+        }
+    }
+
+    pub fn check_assignable(&mut self, name: &Symbol, line: u32) {
+        let is_const = match self.scopes.iter().rev().find_map(|hm| hm.get(name)) {
+            Some(local) => local.state == VariableState::Const,
+            None => self.global_consts.contains(name),
+        };
+        if is_const {
+            self.errors.push(ErrorDetail::new(
+                line,
+                format!("Cannot assign to constant '{name}'."),
+            ));
+        }
+    }
+
+    pub fn check_initialized(&mut self, name: &Symbol, line: u32) {
+        if self.scopes.last().is_some_and(|hm| {
+            hm.get(name)
+                .is_some_and(|local| local.state == VariableState::Declared)
+        }) {
             self.errors.push(ErrorDetail::new(
                 line,
                 "Can't read local variable in its own initializer.",
@@ -99,16 +192,21 @@ impl Scopes {
         }
     }
 
-    pub fn resolve_local(&self, name: &str) -> Option<u32> {
+    pub fn resolve_local(&self, name: &Symbol) -> Option<Slot> {
         self.scopes
             .iter()
             .rev()
-            .position(|hm| hm.contains_key(name))
-            .map(|v| v as u32)
+            .enumerate()
+            .find_map(|(distance, hm)| {
+                hm.get(name).map(|local| Slot {
+                    distance: distance as u32,
+                    index: local.index,
+                })
+            })
     }
 
-    pub fn into_errors(self) -> Vec<ErrorDetail> {
-        self.errors
+    pub fn into_diagnostics(self) -> (Vec<ErrorDetail>, Vec<ErrorDetail>) {
+        (self.errors, self.warnings)
     }
 }
 
@@ -116,16 +214,92 @@ pub trait Resolve {
     fn resolve(&mut self, scopes: &mut Scopes);
 }
 
+/// Resolves the statements of a script, without reporting warnings.
 pub fn resolve(statements: &mut [Box<dyn Statement>]) -> Result<()> {
-    let mut scopes = Scopes::new();
+    let options = ResolveOptions {
+        is_script: true,
+        ..ResolveOptions::default()
+    };
+    resolve_with_warnings(statements, options).map(|_| ())
+}
+
+/// Resolves like `resolve` and returns the warnings, which don't stop the program from
+/// running. Shadowing is only reported when asked for, since it's often intended.
+pub fn resolve_with_warnings(
+    statements: &mut [Box<dyn Statement>],
+    options: ResolveOptions,
+) -> Result<Vec<ErrorDetail>> {
+    let mut scopes = Scopes::new(options);
     for statement in statements {
         statement.resolve(&mut scopes)
     }
 
-    let errors = scopes.into_errors();
-    if errors.len() > 0 {
+    let (errors, warnings) = scopes.into_diagnostics();
+    if !errors.is_empty() {
         Err(Error::ResolverErrors(errors))
     } else {
-        Ok(())
+        Ok(warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::scan_tokens};
+
+    fn warnings(source: &str) -> Result<Vec<String>> {
+        let tokens = scan_tokens(source)?;
+        let mut statements = Parser::new(&tokens).parse()?;
+        let options = ResolveOptions {
+            warn_shadowing: true,
+            ..ResolveOptions::default()
+        };
+        let warnings = resolve_with_warnings(&mut statements, options)?;
+        Ok(warnings.iter().map(|warning| warning.to_string()).collect())
+    }
+
+    #[test]
+    fn test_shadowing_warnings() {
+        let source = "\
+var a = 1;
+{
+  var a = 2;
+  var b = 3;
+  {
+    var b = 4;
+    {
+      var a = 5;
+    }
+  }
+}
+fun f(b) {
+  var c;
+  fun g() { var c; }
+}";
+        // shadowing a global isn't reported, globals can be redefined anyway
+        assert_eq!(
+            warnings(source).unwrap(),
+            vec![
+                "[ line 6 ] : Variable 'b' shadows an outer binding.",
+                "[ line 8 ] : Variable 'a' shadows an outer binding.",
+                "[ line 14 ] : Variable 'c' shadows an outer binding.",
+            ]
+        );
+
+        let mut statements = Parser::new(&scan_tokens(source).unwrap()).parse().unwrap();
+        assert!(
+            resolve_with_warnings(&mut statements, ResolveOptions::default())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_redeclaration_is_still_an_error() {
+        let err = warnings("{\n  var a = 1;\n  var a = 2;\n}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Resolver error(s):\n[ line 3 ] : Already a variable with this name in this scope.\n"
+        );
     }
 }
@@ -1,11 +1,17 @@
 use crate::{
     ast::{
-        AssignExpression, BinaryExpression, CallExpression, GetExpression, GroupingExpression, LiteralExpression, LogicalExpression, NegExpression, NilExpression, NotExpression, SetExpression, SuperExpression, ThisExpression, VariableExpression
+        AssignExpression, BinaryExpression, BitNotExpression, CallExpression, FunctionExpression,
+        GetExpression, GroupingExpression, IndexExpression, IndexSetExpression, LetExpression,
+        ListExpression, LiteralExpression, LogicalExpression, MapExpression, NegExpression,
+        NilExpression, NotExpression, SetExpression, SuperExpression, TernaryExpression,
+        ThisExpression, VariableExpression,
     },
-    error::ErrorDetail, resolver::ClassType,
+    error::ErrorDetail,
+    resolver::ClassType,
+    symbol::Symbol,
 };
 
-use super::{Resolve, Scopes};
+use super::{resolve_stmt::resolve_function, FunctionType, Resolve, Scopes};
 
 impl Resolve for NilExpression {
     fn resolve(&mut self, _scopes: &mut Scopes) {
@@ -25,6 +31,12 @@ impl Resolve for NegExpression {
     }
 }
 
+impl Resolve for BitNotExpression {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        self.expression.resolve(scopes);
+    }
+}
+
 impl Resolve for NotExpression {
     fn resolve(&mut self, scopes: &mut Scopes) {
         self.0.resolve(scopes);
@@ -47,14 +59,15 @@ impl Resolve for BinaryExpression {
 impl Resolve for VariableExpression {
     fn resolve(&mut self, scopes: &mut Scopes) {
         scopes.check_initialized(&self.name, self.line);
-        self.maybe_distance = scopes.resolve_local(&self.name);
+        self.maybe_slot = scopes.resolve_local(&self.name);
     }
 }
 
 impl Resolve for AssignExpression {
     fn resolve(&mut self, scopes: &mut Scopes) {
         self.value.resolve(scopes);
-        self.maybe_distance = scopes.resolve_local(&self.name);
+        scopes.check_assignable(&self.name, self.line);
+        self.maybe_slot = scopes.resolve_local(&self.name);
     }
 }
 
@@ -65,12 +78,34 @@ impl Resolve for LogicalExpression {
     }
 }
 
+impl Resolve for TernaryExpression {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        self.condition.resolve(scopes);
+        self.then_branch.resolve(scopes);
+        self.else_branch.resolve(scopes);
+    }
+}
+
 impl Resolve for CallExpression {
     fn resolve(&mut self, scopes: &mut Scopes) {
         self.callee.resolve(scopes);
         for arg in &mut self.arguments {
             arg.resolve(scopes);
         }
+        for (_, arg) in &mut self.keyword_arguments {
+            arg.resolve(scopes);
+        }
+    }
+}
+
+impl Resolve for FunctionExpression {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        resolve_function(
+            &self.parameters,
+            &mut self.statements,
+            FunctionType::Function,
+            scopes,
+        );
     }
 }
 
@@ -87,6 +122,38 @@ impl Resolve for SetExpression {
     }
 }
 
+impl Resolve for ListExpression {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        for element in &mut self.elements {
+            element.resolve(scopes);
+        }
+    }
+}
+
+impl Resolve for MapExpression {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        for (key, value) in &mut self.entries {
+            key.resolve(scopes);
+            value.resolve(scopes);
+        }
+    }
+}
+
+impl Resolve for IndexExpression {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        self.object.resolve(scopes);
+        self.index.resolve(scopes);
+    }
+}
+
+impl Resolve for IndexSetExpression {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        self.value.resolve(scopes);
+        self.object.resolve(scopes);
+        self.index.resolve(scopes);
+    }
+}
+
 impl Resolve for ThisExpression {
     fn resolve(&mut self, scopes: &mut Scopes) {
         if scopes.class_types.is_empty() {
@@ -95,7 +162,7 @@ impl Resolve for ThisExpression {
                 "Can't use 'this' outside of a class.",
             ));
         } else {
-            self.maybe_distance = scopes.resolve_local("this");
+            self.maybe_slot = scopes.resolve_local(&Symbol::intern("this"));
         }
     }
 }
@@ -108,13 +175,27 @@ impl Resolve for SuperExpression {
                 "Can't use 'super' outside of a class.",
             ));
         }
-        if scopes.class_types.last().is_some_and(|ct| *ct != ClassType::Subclass) {
+        if scopes
+            .class_types
+            .last()
+            .is_some_and(|ct| *ct != ClassType::Subclass)
+        {
             scopes.errors.push(ErrorDetail::new(
                 self.line,
                 "Can't use 'super' in a class with no superclass.",
             ));
         }
 
-        self.maybe_distance = scopes.resolve_local("super");
+        self.maybe_slot = scopes.resolve_local(&Symbol::intern("super"));
+    }
+}
+
+impl Resolve for LetExpression {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        self.value.resolve(scopes);
+        scopes.begin_scope();
+        scopes.define(&self.name);
+        self.body.resolve(scopes);
+        scopes.end_scope();
     }
 }
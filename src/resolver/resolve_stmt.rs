@@ -2,10 +2,12 @@ use std::rc::Rc;
 
 use crate::{
     ast::{
-        BlockStatement, ClassStatement, ExpressionStatement, FunctionStatement, IfStatement,
-        PrintStatement, ReturnStatement, Statement, VarStatement, WhileStatement,
+        BlockStatement, BreakStatement, CallExpression, ClassStatement, ContinueStatement,
+        DoWhileStatement, ExpressionStatement, ForEachStatement, FunctionStatement, IfStatement,
+        Parameter, PrintStatement, ReturnStatement, Statement, VarStatement, WhileStatement,
     },
     error::ErrorDetail,
+    symbol::Symbol,
 };
 
 use super::{ClassType, FunctionType, Resolve, Scopes};
@@ -18,7 +20,9 @@ fn resolve_statements(statements: &mut [Box<dyn Statement>], scopes: &mut Scopes
 
 impl Resolve for PrintStatement {
     fn resolve(&mut self, scopes: &mut Scopes) {
-        self.expression.resolve(scopes);
+        for expression in &mut self.expressions {
+            expression.resolve(scopes);
+        }
     }
 }
 
@@ -34,7 +38,12 @@ impl Resolve for VarStatement {
         if let Some(i) = self.initializer.as_mut() {
             i.resolve(scopes);
         }
-        scopes.define(&self.name);
+        if self.is_const {
+            scopes.define_const(&self.name);
+        } else {
+            scopes.define(&self.name);
+            scopes.define_global(&self.name);
+        }
     }
 }
 
@@ -59,26 +68,80 @@ impl Resolve for IfStatement {
 impl Resolve for WhileStatement {
     fn resolve(&mut self, scopes: &mut Scopes) {
         self.condition.resolve(scopes);
+        scopes.begin_loop();
+        self.body.resolve(scopes);
+        scopes.end_loop();
+        if let Some(increment) = &mut self.maybe_increment {
+            increment.resolve(scopes);
+        }
+    }
+}
+
+/// The loop variable lives in a scope of its own, which is created anew for every element.
+impl Resolve for ForEachStatement {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        self.iterable.resolve(scopes);
+        scopes.begin_scope();
+        scopes.declare(&self.name, self.line);
+        scopes.define(&self.name);
+        scopes.begin_loop();
         self.body.resolve(scopes);
+        scopes.end_loop();
+        scopes.end_scope();
+    }
+}
+
+impl Resolve for DoWhileStatement {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        scopes.begin_loop();
+        self.body.resolve(scopes);
+        scopes.end_loop();
+        self.condition.resolve(scopes);
+    }
+}
+
+impl Resolve for BreakStatement {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        if scopes.loop_depth == 0 {
+            scopes.errors.push(ErrorDetail::new(
+                self.line,
+                "Can't use 'break' outside of a loop.",
+            ));
+        }
+    }
+}
+
+impl Resolve for ContinueStatement {
+    fn resolve(&mut self, scopes: &mut Scopes) {
+        if scopes.loop_depth == 0 {
+            scopes.errors.push(ErrorDetail::new(
+                self.line,
+                "Can't use 'continue' outside of a loop.",
+            ));
+        }
     }
 }
 
 pub fn resolve_function(
-    fn_statement: &mut FunctionStatement,
+    parameters: &[Parameter],
+    statements: &mut Rc<Vec<Box<dyn Statement>>>,
     fn_type: FunctionType,
     scopes: &mut Scopes,
 ) {
     scopes.begin_function(fn_type);
+    // loops don't extend into the function body
+    let enclosing_loop_depth = std::mem::take(&mut scopes.loop_depth);
     scopes.begin_scope();
-    for param in &fn_statement.parameters {
+    for param in parameters {
         scopes.declare(&param.name, param.line);
         scopes.define(&param.name);
     }
-    let mut_statements = Rc::get_mut(&mut fn_statement.statements).unwrap();
+    let mut_statements = Rc::get_mut(statements).unwrap();
     for statement in mut_statements {
         statement.resolve(scopes);
     }
     scopes.end_scope();
+    scopes.loop_depth = enclosing_loop_depth;
     scopes.end_function();
 }
 
@@ -87,7 +150,12 @@ impl Resolve for FunctionStatement {
         scopes.declare(&self.name, self.line);
         scopes.define(&self.name);
 
-        resolve_function(self, FunctionType::Function, scopes);
+        resolve_function(
+            &self.parameters,
+            &mut self.statements,
+            FunctionType::Function,
+            scopes,
+        );
     }
 }
 
@@ -105,12 +173,15 @@ impl Resolve for ReturnStatement {
                 ));
             }
             expression.resolve(scopes);
+            self.is_tail_call = expression.as_any().is::<CallExpression>();
         }
-        if scopes.function_types.len() == 0 {
-            scopes.errors.push(ErrorDetail::new(
-                self.line,
-                "Can't return from top-level code.",
-            ));
+        if scopes.function_types.is_empty() {
+            let message = match (scopes.options.is_script, &self.maybe_expression) {
+                (true, None) => return,
+                (true, Some(_)) => "Can't return a value from top-level code.",
+                (false, _) => "Can't return from top-level code.",
+            };
+            scopes.errors.push(ErrorDetail::new(self.line, message));
         };
     }
 }
@@ -136,18 +207,23 @@ impl Resolve for ClassStatement {
             superclass.resolve(scopes);
 
             scopes.begin_scope();
-            scopes.define("super");
+            scopes.define(&Symbol::intern("super"));
         }
 
         scopes.begin_scope();
-        scopes.define("this");
-        for method in Rc::get_mut(&mut self.methods).unwrap().values_mut() {
-            let declaration = if method.name == "init" {
+        scopes.define(&Symbol::intern("this"));
+        for method in Rc::get_mut(&mut self.methods).unwrap().iter_mut() {
+            let declaration = if &*method.name == "init" {
                 FunctionType::Initializer
             } else {
                 FunctionType::Method
             };
-            resolve_function(method, declaration, scopes);
+            resolve_function(
+                &method.parameters,
+                &mut method.statements,
+                declaration,
+                scopes,
+            );
         }
         // end this scope
         scopes.end_scope();
@@ -157,5 +233,15 @@ impl Resolve for ClassStatement {
         }
 
         scopes.end_class();
+
+        // static methods are resolved outside of the class so they have no access to 'this'
+        for method in Rc::get_mut(&mut self.static_methods).unwrap().iter_mut() {
+            resolve_function(
+                &method.parameters,
+                &mut method.statements,
+                FunctionType::Method,
+                scopes,
+            );
+        }
     }
 }
@@ -1,4 +1,6 @@
-use itertools::Itertools;
+use std::str::Chars;
+
+use itertools::{Itertools, MultiPeek};
 use phf::phf_map;
 
 use crate::error::{Error, ErrorDetail};
@@ -10,12 +12,17 @@ use crate::Result;
 
 static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "and" => And,
+    "break" => Break,
     "class" => Class,
+    "const" => Const,
+    "continue" => Continue,
+    "do" => Do,
     "else" => Else,
     "false" => False,
     "for" => For,
     "fun" => Fun,
     "if" => If,
+    "in" => In,
     "nil" => Nil,
     "or" => Or,
     "print" => Print,
@@ -27,14 +34,101 @@ static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "while" => While,
 };
 
+/// Character iterator that keeps track of the column of the last consumed character.
+struct Cursor<'a> {
+    source: &'a str,
+    chars: MultiPeek<Chars<'a>>,
+    column: u32,
+    /// Byte offset in the source of the next character.
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.chars().multipeek(),
+            column: 0,
+            offset: 0,
+        }
+    }
+
+    /// Continues at the start of the line beginning at `offset`.
+    fn restart_line_at(&mut self, offset: usize) {
+        self.chars = self.source[offset..].chars().multipeek();
+        self.column = 0;
+        self.offset = offset;
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    fn reset_peek(&mut self) {
+        self.chars.reset_peek();
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let next = self.chars.next();
+        match next {
+            Some('\n') => self.column = 0,
+            Some(_) => self.column += 1,
+            None => (),
+        }
+        self.offset += next.map_or(0, char::len_utf8);
+        next
+    }
+}
+
+/// Reads the rest of a `\u{1F600}` escape, one to six hex digits in braces, after the `u`.
+/// Returns `None` if it's malformed or not a Unicode scalar value, like a surrogate.
+fn unicode_escape(chars: &mut Cursor, lexeme: &mut std::string::String) -> Option<char> {
+    if chars.peek() != Some(&'{') {
+        chars.reset_peek();
+        return None;
+    }
+    lexeme.extend(chars.next());
+    let mut digits = std::string::String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_ascii_hexdigit() {
+            break;
+        }
+        digits.push(c);
+        lexeme.extend(chars.next());
+    }
+    chars.reset_peek();
+    if chars.peek() != Some(&'}') {
+        chars.reset_peek();
+        return None;
+    }
+    lexeme.extend(chars.next());
+    if digits.is_empty() || digits.len() > 6 {
+        return None;
+    }
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+}
+
+/// Underscores may only appear between two digits.
+fn has_valid_separators(digits: &str) -> bool {
+    !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__")
+}
+
 pub fn scan_tokens(source: &str) -> Result<Vec<Token>> {
     let mut tokens = vec![];
     let mut errors = vec![];
     let mut line = 1;
 
-    let mut chars = source.chars().multipeek();
+    let mut chars = Cursor::new(source);
     while let Some(c) = chars.next() {
-        let mut add_token = |ty: TokenType| tokens.push(Token::new(ty, c.to_string(), None, line));
+        let column = chars.column;
+        let mut add_token =
+            |ty: TokenType| tokens.push(Token::new(ty, c.to_string(), None, line, column));
 
         match c {
             // one char tokens
@@ -42,48 +136,112 @@ pub fn scan_tokens(source: &str) -> Result<Vec<Token>> {
             ')' => add_token(RightParen),
             '{' => add_token(LeftBrace),
             '}' => add_token(RightBrace),
+            '[' => add_token(LeftBracket),
+            ']' => add_token(RightBracket),
             ',' => add_token(Comma),
-            '.' => add_token(Dot),
-            '-' => add_token(Minus),
-            '+' => add_token(Plus),
             ';' => add_token(Semicolon),
-            '*' => add_token(Star),
+            '%' => add_token(Percent),
+            ':' => add_token(Colon),
+            '&' => add_token(Amp),
+            '|' => add_token(Pipe),
+            '^' => add_token(Caret),
+            '~' => add_token(Tilde),
             // two char tokens
-            '!' => {
+            '-' => {
                 if let Some('=') = chars.peek() {
                     chars.next();
-                    tokens.push(Token::new(BangEqual, "!=".to_owned(), None, line));
+                    tokens.push(Token::new(MinusEqual, "-=", None, line, column));
                 } else {
-                    tokens.push(Token::new(Bang, c.to_string(), None, line));
+                    tokens.push(Token::new(Minus, c.to_string(), None, line, column));
                 }
             }
-            '=' => {
+            '+' => {
                 if let Some('=') = chars.peek() {
                     chars.next();
-                    tokens.push(Token::new(EqualEqual, "==".to_owned(), None, line));
+                    tokens.push(Token::new(PlusEqual, "+=", None, line, column));
+                } else {
+                    tokens.push(Token::new(Plus, c.to_string(), None, line, column));
+                }
+            }
+            '*' => match chars.peek() {
+                Some('=') => {
+                    chars.next();
+                    tokens.push(Token::new(StarEqual, "*=", None, line, column));
+                }
+                Some('*') => {
+                    chars.next();
+                    tokens.push(Token::new(StarStar, "**", None, line, column));
+                }
+                _ => {
+                    tokens.push(Token::new(Star, c.to_string(), None, line, column));
+                }
+            },
+            '.' => {
+                let is_dot_dot = chars.peek() == Some(&'.');
+                if is_dot_dot && chars.peek() == Some(&'.') {
+                    chars.next();
+                    chars.next();
+                    tokens.push(Token::new(DotDotDot, "...", None, line, column));
+                } else if is_dot_dot {
+                    chars.next();
+                    tokens.push(Token::new(DotDot, "..", None, line, column));
                 } else {
-                    tokens.push(Token::new(Equal, c.to_string(), None, line));
+                    chars.reset_peek();
+                    tokens.push(Token::new(Dot, c.to_string(), None, line, column));
                 }
             }
-            '<' => {
+            '?' => match chars.peek() {
+                Some('?') => {
+                    chars.next();
+                    tokens.push(Token::new(QuestionQuestion, "??", None, line, column));
+                }
+                Some('.') => {
+                    chars.next();
+                    tokens.push(Token::new(QuestionDot, "?.", None, line, column));
+                }
+                _ => tokens.push(Token::new(Question, c.to_string(), None, line, column)),
+            },
+            '!' => {
                 if let Some('=') = chars.peek() {
                     chars.next();
-                    tokens.push(Token::new(LessEqual, "<=".to_owned(), None, line));
+                    tokens.push(Token::new(BangEqual, "!=", None, line, column));
                 } else {
-                    tokens.push(Token::new(Less, c.to_string(), None, line));
+                    tokens.push(Token::new(Bang, c.to_string(), None, line, column));
                 }
             }
-            '>' => {
+            '=' => {
                 if let Some('=') = chars.peek() {
                     chars.next();
-                    tokens.push(Token::new(GreaterEqual, ">=".to_owned(), None, line));
+                    tokens.push(Token::new(EqualEqual, "==", None, line, column));
                 } else {
-                    tokens.push(Token::new(Greater, c.to_string(), None, line));
+                    tokens.push(Token::new(Equal, c.to_string(), None, line, column));
                 }
             }
+            '<' => match chars.peek() {
+                Some('=') => {
+                    chars.next();
+                    tokens.push(Token::new(LessEqual, "<=", None, line, column));
+                }
+                Some('<') => {
+                    chars.next();
+                    tokens.push(Token::new(LessLess, "<<", None, line, column));
+                }
+                _ => tokens.push(Token::new(Less, c.to_string(), None, line, column)),
+            },
+            '>' => match chars.peek() {
+                Some('=') => {
+                    chars.next();
+                    tokens.push(Token::new(GreaterEqual, ">=", None, line, column));
+                }
+                Some('>') => {
+                    chars.next();
+                    tokens.push(Token::new(GreaterGreater, ">>", None, line, column));
+                }
+                _ => tokens.push(Token::new(Greater, c.to_string(), None, line, column)),
+            },
             // comment or slash
-            '/' => {
-                if let Some('/') = chars.peek() {
+            '/' => match chars.peek() {
+                Some('/') => {
                     chars.next();
                     while let Some(&next_char) = chars.peek() {
                         if next_char == '\n' {
@@ -92,43 +250,183 @@ pub fn scan_tokens(source: &str) -> Result<Vec<Token>> {
                             chars.next();
                         }
                     }
-                } else {
-                    tokens.push(Token::new(Slash, c.to_string(), None, line));
                 }
-            }
+                Some('*') => {
+                    chars.next();
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match chars.next() {
+                            Some('/') if chars.peek() == Some(&'*') => {
+                                chars.next();
+                                depth += 1;
+                            }
+                            Some('*') if chars.peek() == Some(&'/') => {
+                                chars.next();
+                                depth -= 1;
+                            }
+                            Some('\n') => line += 1,
+                            Some(_) => (),
+                            None => {
+                                errors.push(ErrorDetail::new_with_col(
+                                    line,
+                                    chars.column + 1,
+                                    "Unterminated block comment.",
+                                ));
+                                break;
+                            }
+                        }
+                    }
+                }
+                Some('=') => {
+                    chars.next();
+                    tokens.push(Token::new(SlashEqual, "/=", None, line, column));
+                }
+                _ => tokens.push(Token::new(Slash, c.to_string(), None, line, column)),
+            },
             ' ' | '\r' | '\t' => (),
             '\n' => line += 1,
             '"' => {
+                let mut lexeme = std::string::String::new();
                 let mut string_string = std::string::String::new();
+                let (start_line, start_offset, errors_before) = (line, chars.offset, errors.len());
 
                 while chars.peek().is_some_and(|c| *c != '"') {
                     let next_char = chars.next().unwrap();
+                    lexeme.push(next_char);
+                    if next_char == '\\' {
+                        let escape_column = chars.column;
+                        let Some(escaped) = chars.next() else {
+                            break;
+                        };
+                        lexeme.push(escaped);
+                        match escaped {
+                            'n' => string_string.push('\n'),
+                            't' => string_string.push('\t'),
+                            'r' => string_string.push('\r'),
+                            '\\' => string_string.push('\\'),
+                            '"' => string_string.push('"'),
+                            '0' => string_string.push('\0'),
+                            'u' => match unicode_escape(&mut chars, &mut lexeme) {
+                                Some(scalar) => string_string.push(scalar),
+                                None => errors.push(ErrorDetail::new_with_col(
+                                    line,
+                                    escape_column,
+                                    "Invalid unicode escape.",
+                                )),
+                            },
+                            _ => {
+                                if escaped == '\n' {
+                                    line += 1;
+                                }
+                                errors.push(ErrorDetail::new_with_col(
+                                    line,
+                                    escape_column,
+                                    "Invalid escape sequence.",
+                                ));
+                            }
+                        }
+                        continue;
+                    }
                     if next_char == '\n' {
                         line += 1;
-                        dbg!(line);
                     }
                     string_string.push(next_char);
                 }
 
+                // the loop condition peeked at the closing quote already
+                chars.reset_peek();
                 if chars.peek().is_none() {
-                    errors.push(ErrorDetail::new(line, "Unterminated string."));
-                    break;
+                    // strings can span lines, but one that never ends was most likely meant to
+                    // end on its first line, so scanning goes on with the next one to find the
+                    // errors after it too
+                    errors.truncate(errors_before);
+                    errors.push(ErrorDetail::new_with_col(
+                        start_line,
+                        column,
+                        "Unterminated string.",
+                    ));
+                    match source[start_offset..].find('\n') {
+                        Some(newline) => {
+                            chars.restart_line_at(start_offset + newline + 1);
+                            line = start_line + 1;
+                            continue;
+                        }
+                        None => break,
+                    }
                 }
 
                 chars.next(); // consume closing "
 
                 tokens.push(Token::new(
                     String,
-                    string_string.clone(),
+                    lexeme,
                     Some(Literal::String(string_string)),
-                    line,
+                    start_line,
+                    column,
                 ));
             }
             _ => {
-                if c.is_ascii_digit() {
+                let maybe_radix = match (c, chars.peek()) {
+                    ('0', Some('x' | 'X')) => Some((16, "hex")),
+                    ('0', Some('b' | 'B')) => Some((2, "binary")),
+                    _ => None,
+                };
+                chars.reset_peek();
+
+                if let Some((radix, radix_name)) = maybe_radix {
                     let mut num_string = c.to_string();
+                    num_string.push(chars.next().unwrap()); // consume 'x' or 'b'
 
-                    while chars.peek().is_some_and(|pc| pc.is_ascii_digit()) {
+                    let mut digits = std::string::String::new();
+                    while chars
+                        .peek()
+                        .is_some_and(|pc| pc.is_ascii_alphanumeric() || *pc == '_')
+                    {
+                        digits.push(chars.next().unwrap());
+                    }
+                    num_string.push_str(&digits);
+
+                    if !has_valid_separators(&digits) {
+                        errors.push(ErrorDetail::new_with_col(
+                            line,
+                            column,
+                            "Invalid digit separator.",
+                        ));
+                        continue;
+                    }
+
+                    let maybe_value = (!digits.is_empty())
+                        .then(|| {
+                            digits
+                                .chars()
+                                .filter(|&d| d != '_')
+                                .try_fold(0.0, |acc, d| {
+                                    d.to_digit(radix).map(|d| acc * radix as f64 + d as f64)
+                                })
+                        })
+                        .flatten();
+                    if let Some(value) = maybe_value {
+                        tokens.push(Token::new(
+                            Number,
+                            num_string,
+                            Some(Literal::Number(value)),
+                            line,
+                            column,
+                        ));
+                    } else {
+                        errors.push(ErrorDetail::new_with_col(
+                            line,
+                            column,
+                            format!("Invalid {radix_name} literal."),
+                        ));
+                    }
+                } else if c.is_ascii_digit() {
+                    let mut num_string = c.to_string();
+
+                    while chars
+                        .peek()
+                        .is_some_and(|pc| pc.is_ascii_digit() || *pc == '_')
+                    {
                         let t = chars.next().unwrap();
                         num_string.push(t);
                     }
@@ -141,15 +439,28 @@ pub fn scan_tokens(source: &str) -> Result<Vec<Token>> {
                     {
                         num_string.push(chars.next().unwrap()); // consume '.'
 
-                        while chars.peek().is_some_and(|pc| pc.is_ascii_digit()) {
+                        while chars
+                            .peek()
+                            .is_some_and(|pc| pc.is_ascii_digit() || *pc == '_')
+                        {
                             num_string.push(chars.next().unwrap());
                         }
                     }
 
-                    let parse_res = num_string.parse::<f64>();
+                    if !num_string.split('.').all(has_valid_separators) {
+                        errors.push(ErrorDetail::new_with_col(
+                            line,
+                            column,
+                            "Invalid digit separator.",
+                        ));
+                        continue;
+                    }
+
+                    let parse_res = num_string.replace('_', "").parse::<f64>();
                     if let Err(_) = parse_res {
-                        errors.push(ErrorDetail::new(
+                        errors.push(ErrorDetail::new_with_col(
                             line,
+                            column,
                             format!("Could not parse number: {num_string}."),
                         ));
                         continue;
@@ -160,6 +471,7 @@ pub fn scan_tokens(source: &str) -> Result<Vec<Token>> {
                         num_string,
                         Some(Literal::Number(parse_res.unwrap())),
                         line,
+                        column,
                     ));
                 } else if c.is_ascii_alphabetic() || c == '_' {
                     let mut identifier_string = c.to_string();
@@ -172,20 +484,27 @@ pub fn scan_tokens(source: &str) -> Result<Vec<Token>> {
                     }
 
                     if let Some(ty) = KEYWORDS.get(&identifier_string) {
-                        tokens.push(Token::new(*ty, identifier_string, None, line));
+                        tokens.push(Token::new(*ty, identifier_string, None, line, column));
                     } else {
-                        tokens.push(Token::new(Identifier, identifier_string, None, line));
+                        tokens.push(Token::new(
+                            Identifier,
+                            identifier_string,
+                            None,
+                            line,
+                            column,
+                        ));
                     }
                 } else {
-                    errors.push(ErrorDetail::new(
+                    errors.push(ErrorDetail::new_with_col(
                         line,
+                        column,
                         format!("Unexpected character: {c}."),
                     ));
                 }
             }
         }
     }
-    tokens.push(Token::new(Eof, "".to_string(), None, line));
+    tokens.push(Token::new(Eof, "", None, line, chars.column + 1));
 
     if errors.is_empty() {
         Ok(tokens)
@@ -201,6 +520,7 @@ mod tests {
     use insta::{assert_debug_snapshot, glob};
 
     use super::*;
+    use crate::token::Lexeme;
 
     #[test]
     fn test_scanner() {
@@ -209,4 +529,17 @@ mod tests {
             assert_debug_snapshot!(scan_tokens(&input));
         });
     }
+
+    #[test]
+    fn test_only_names_are_interned() {
+        let tokens = scan_tokens("var name = \"text\" + 12;").unwrap();
+        let interned: Vec<_> = tokens
+            .iter()
+            .map(|token| matches!(token.lexeme, Lexeme::Name(_)))
+            .collect();
+        assert_eq!(
+            interned,
+            [true, true, false, false, false, false, false, false]
+        );
+    }
 }
@@ -0,0 +1,95 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    rc::Rc,
+};
+
+thread_local! {
+    /// Every string that was interned so far. Entries are never removed, so a symbol made
+    /// from the same text later is the same allocation.
+    static INTERNER: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+
+/// Interned text of a token, used for the names of variables, fields and methods. Symbols
+/// with the same text share one allocation, so they are compared and hashed by pointer.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+    pub fn intern(text: &str) -> Self {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            match interner.get(text) {
+                Some(interned) => Symbol(interned.clone()),
+                None => {
+                    let interned: Rc<str> = Rc::from(text);
+                    interner.insert(interned.clone());
+                    Symbol(interned)
+                }
+            }
+        })
+    }
+
+    /// The symbol for `text` if it was interned before. Lookups of names only known at
+    /// runtime use this, so they don't grow the interner; a name that was never interned
+    /// can't be the name of anything.
+    pub fn get(text: &str) -> Option<Self> {
+        INTERNER.with(|interner| interner.borrow().get(text).cloned().map(Symbol))
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).cast::<u8>().hash(state);
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let a = Symbol::intern("name");
+        let b = Symbol::intern(&String::from("name"));
+        assert!(Rc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, b);
+        assert_ne!(a, Symbol::intern("other"));
+        assert_eq!(&*a, "name");
+        assert_eq!(a.to_string(), "name");
+        assert_eq!(format!("{a:?}"), "\"name\"");
+        assert_eq!(Symbol::get("name"), Some(a));
+        assert_eq!(Symbol::get("never interned"), None);
+    }
+}
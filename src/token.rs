@@ -1,5 +1,9 @@
+use std::{fmt, ops::Deref};
+
 use strum::Display;
 
+use crate::symbol::Symbol;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
 pub enum TokenType {
     // Single-character tokens.
@@ -7,13 +11,27 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
+    MinusEqual,
     Plus,
+    PlusEqual,
     Semicolon,
     Slash,
+    SlashEqual,
     Star,
+    StarStar,
+    StarEqual,
+    Percent,
+    Question,
+    Colon,
+    Amp,
+    Pipe,
+    Caret,
+    Tilde,
 
     // One or two character tokens.
     Bang,
@@ -22,8 +40,14 @@ pub enum TokenType {
     EqualEqual,
     Greater,
     GreaterEqual,
+    GreaterGreater,
     Less,
     LessEqual,
+    LessLess,
+    QuestionQuestion,
+    QuestionDot,
+    DotDot,
+    DotDotDot,
 
     // Literals.
     Identifier,
@@ -32,12 +56,17 @@ pub enum TokenType {
 
     // Keywords.
     And,
+    Break,
     Class,
+    Const,
+    Continue,
+    Do,
     Else,
     False,
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
@@ -56,21 +85,102 @@ pub enum Literal {
     String(String),
 }
 
+/// Text of a token. Identifiers and keywords are interned, since the parser turns them into
+/// names; literals and punctuation keep their own text, so the strings and numbers a long
+/// session scans don't pile up in the interner.
+#[derive(Clone)]
+pub enum Lexeme {
+    Name(Symbol),
+    Text(String),
+}
+
+impl Deref for Lexeme {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Lexeme::Name(symbol) => symbol,
+            Lexeme::Text(text) => text,
+        }
+    }
+}
+
+impl fmt::Display for Lexeme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &**self)
+    }
+}
+
+impl fmt::Debug for Lexeme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", &**self)
+    }
+}
+
 #[derive(Debug)]
 pub struct Token {
     pub ty: TokenType,
-    pub lexeme: String,
+    pub lexeme: Lexeme,
     pub literal: Option<Literal>,
     pub line: u32,
+    pub column: u32,
 }
 
 impl Token {
-    pub fn new(ty: TokenType, lexeme: String, literal: Option<Literal>, line: u32) -> Self {
+    pub fn new(
+        ty: TokenType,
+        lexeme: impl Into<String>,
+        literal: Option<Literal>,
+        line: u32,
+        column: u32,
+    ) -> Self {
+        use TokenType::*;
+        let lexeme = lexeme.into();
+        let lexeme = match ty {
+            Identifier | And | Break | Class | Const | Continue | Do | Else | False | Fun | For
+            | If | In | Nil | Or | Print | Return | Super | This | True | Var | While => {
+                Lexeme::Name(Symbol::intern(&lexeme))
+            }
+            _ => Lexeme::Text(lexeme),
+        };
         Self {
             ty,
             lexeme,
             literal,
             line,
+            column,
+        }
+    }
+
+    /// The interned name of an identifier or keyword.
+    pub fn symbol(&self) -> Symbol {
+        match &self.lexeme {
+            Lexeme::Name(symbol) => symbol.clone(),
+            Lexeme::Text(text) => Symbol::intern(text),
+        }
+    }
+
+    /// The token as it was written, for error messages. The lexeme of a string is its
+    /// contents, so the quotes are added back.
+    pub fn source_text(&self) -> String {
+        match self.ty {
+            TokenType::String => format!("\"{}\"", self.lexeme),
+            _ => self.lexeme.to_string(),
+        }
+    }
+}
+
+/// One line per token for `--tokens`: position, type, lexeme and the literal value if any.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} {} {:?}",
+            self.line, self.column, self.ty, self.lexeme
+        )?;
+        if let Some(literal) = &self.literal {
+            write!(f, " {literal:?}")?;
         }
+        Ok(())
     }
 }
@@ -0,0 +1,172 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+fn run_with_stdin(input: &str) -> std::process::Output {
+    run_with_args_and_stdin(&[], input)
+}
+
+fn run_with_args_and_stdin(args: &[&str], input: &str) -> std::process::Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_piped_program() {
+    let output = run_with_stdin("var a = 1;\nfun f(x) {\n  return x + a;\n}\nprint f(2);\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+}
+
+#[test]
+fn test_piped_program_error() {
+    let output = run_with_stdin("print 1;\nprint nope;\n");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+}
+
+#[test]
+fn test_json_errors() {
+    let path = std::env::temp_dir().join("rlox_test_json_errors.lox");
+    std::fs::write(&path, "print 1 +;\nvar = 2;\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(["--format", "json"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(!output.status.success());
+    let errors: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    let errors = errors["SyntaxErrors"].as_array().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0]["line"], 1);
+    assert_eq!(errors[0]["column"], 10);
+    assert_eq!(errors[0]["message"], "Expect expression, found ';'.");
+    assert_eq!(errors[1]["line"], 2);
+}
+
+#[test]
+fn test_stack_overflow() {
+    let output = run_with_stdin("fun forever(n) {\n  return 1 + forever(n + 1);\n}\nforever(0);\n");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("[ line 2 ] : Stack overflow."), "{stderr}");
+}
+
+#[test]
+fn test_deep_recursion_through_nested_blocks() {
+    // every block and loop adds to the Rust stack a call takes
+    let program = "fun f(n) {
+  if (n > 0) {
+    { while (true) { for (;;) { { return 1 + f(n - 1); } } } }
+  }
+  return 0;
+}
+print f(995);
+f(2000);
+";
+    let output = run_with_stdin(program);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "995\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("[ line 3 ] : Stack overflow."), "{stderr}");
+}
+
+#[test]
+fn test_max_call_depth() {
+    let program = "fun f(n) { if (n > 0) f(n - 1); }\nf(10);\nprint \"done\";\n";
+    let output = run_with_args_and_stdin(&["--max-call-depth", "5"], program);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("[ line 1 ] : Stack overflow."), "{stderr}");
+
+    let output = run_with_args_and_stdin(&["--max-call-depth", "20"], program);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "done\n");
+}
+
+#[test]
+fn test_dump_ast() {
+    let program = "var a = 1;\nfun add(x) {\n  return x + a;\n}\nprint add(2);\n\
+                   class A {\n  c() {}\n  a() {}\n  class s() {}\n  b() {}\n  class t() {}\n}\n";
+    let output = run_with_args_and_stdin(&["--dump-ast"], program);
+    assert!(output.status.success());
+    insta::assert_snapshot!(String::from_utf8(output.stdout).unwrap());
+}
+
+#[test]
+fn test_tokens() {
+    let path = std::env::temp_dir().join("rlox_test_tokens.lox");
+    std::fs::write(&path, "var s = \"hi\";\nprint s + 1.5; // done\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg("--tokens")
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    insta::assert_snapshot!(String::from_utf8(output.stdout).unwrap());
+}
+
+#[test]
+fn test_max_steps() {
+    let output = run_with_args_and_stdin(
+        &["--max-steps", "100"],
+        "var i = 0;\nwhile (true) i = i + 1;\n",
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("[ line 2 ] : Execution limit exceeded."),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_error_report() {
+    let output = run_with_stdin("var a = 1;\n\tprint a + ;\nprint nope;\n");
+    assert!(!output.status.success());
+    insta::assert_snapshot!(String::from_utf8(output.stderr).unwrap());
+}
+
+#[test]
+fn test_warn_shadowing() {
+    let program = "var a = 1;\n{\n  var a = 2;\n  {\n    var a = 3;\n    print a;\n  }\n}\n";
+    let output = run_with_args_and_stdin(&["--warn-shadowing"], program);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Warning: [ line 5 ] : Variable 'a' shadows an outer binding.\n"
+    );
+
+    // off by default
+    let output = run_with_stdin(program);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_chained_comparisons() {
+    let program = "var x = 5;\nprint 0 <= x < 10;\n";
+    let output = run_with_args_and_stdin(&["--chained-comparisons"], program);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "true\n");
+
+    // an error without the flag
+    let output = run_with_stdin(program);
+    assert!(!output.status.success());
+}
@@ -0,0 +1,47 @@
+use std::{fmt::Display, rc::Rc};
+
+use rlox::{Error, ErrorDetail, Interpreter, LoxCallable, LoxType, Result};
+
+#[derive(Debug)]
+struct Double();
+
+impl Display for Double {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn double>")
+    }
+}
+
+impl LoxCallable for Double {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, arguments: Vec<LoxType>) -> Result<LoxType> {
+        match arguments[0] {
+            LoxType::Number(n) => Ok(LoxType::Number(n * 2.0)),
+            _ => Err(Error::RuntimeError(ErrorDetail::new(
+                0,
+                "Operand must be a number.",
+            ))),
+        }
+    }
+}
+
+#[test]
+fn test_define_native() {
+    let interpreter = Interpreter::new();
+    interpreter.define_native("double", Rc::new(Double()));
+    assert_eq!(
+        interpreter.eval_str("double(21);").unwrap(),
+        LoxType::Number(42.0)
+    );
+    assert_eq!(
+        interpreter.eval_str("double;").unwrap().to_string(),
+        "<native fn double>"
+    );
+    let err = interpreter.run("\n double(\"a\");").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Runtime error: [ line 2 ] : Operand must be a number.\n"
+    );
+}